@@ -1,62 +1,128 @@
-// use std::io::{BufRead, BufReader};
-// use std::io::{BufWriter, Write};
-
-// // Read the stats from all files.
-// // Report how many files were read.
-
-// pub fn read_stats() -> Result<(), Box<dyn std::error::Error>> {
-//     let mut files_read = 0;
-//     let mut total_events = 0;
-//     let mut total_categories = 0;
-//     let mut total_lines = 0;
-
-//     let mut stats: Vec<(String, Vec<(String, u32)>)> = Vec::new();
-
-//     for entry in std::fs::read_dir(".")? {
-//         let entry = entry?;
-//         let path = entry.path();
-//         if path.is_file() {
-//             let file = std::fs::File::open(&path)?;
-//             let reader = BufReader::new(file);
-//             let mut lines = reader.lines();
-//             let mut file_stats: Vec<(String, u32)> = Vec::new();
-//             let mut total = 0;
-//             let mut categories = 0;
-//             let mut file_lines = 0;
-
-//             while let Some(line) = lines.next() {
-//                 let line = line?;
-//                 file_lines += 1;
-//                 if file_lines == 1 {
-//                     total = line.split_whitespace().last().unwrap().parse()?;
-//                 } else {
-//                     let mut parts = line.split(": ");
-//                     let category = parts.next().unwrap().to_string();
-//                     let count = parts.next().unwrap().parse()?;
-//                     file_stats.push((category, count));
-//                     categories += 1;
-//                 }
-//             }
-
-//             stats.push((path.to_string_lossy().to_string(), file_stats));
-//             files_read += 1;
-//             total_events += total;
-//             total_categories += categories;
-//             total_lines += file_lines;
-//         }
-//     }
-
-//     println!("Files read: {}", files_read);
-//     println!("Total events: {}", total_events);
-//     println!("Total categories: {}", total_categories);
-//     println!("Total lines: {}", total_lines);
-
-//     for (file, file_stats) in stats {
-//         println!("File: {}", file);
-//         for (category, count) in file_stats {
-//             println!("{}: {}", category, count);
-//         }
-//     }
-
-//     Ok(())
-// }
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use crate::apps::{Groups, print_groups};
+use crate::counters::{Counter, new_counter_tree};
+use crate::details::bursts_counter;
+use crate::legacy::read_legacy;
+use crate::raw::RawCounts;
+use crate::stats_file::StatsFile;
+use crate::unparsed::Unparsed;
+use crate::writer::{print_raw_stats, print_stats};
+
+// Find the stats files written by `write_stats` in the current directory,
+// both the current `.json` files and the older positional `.txt` files.
+// A `.txt` file that was migrated has a `.json` file by the same name, which is read instead.
+fn stats_files() -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut files = Vec::new();
+
+    for entry in std::fs::read_dir(".")? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if path.is_file()
+            && name.starts_with("atspi-stats-")
+            && (name.ends_with(".json") || name.ends_with(".txt"))
+        {
+            files.push(path);
+        }
+    }
+
+    files.retain(|path| {
+        path.extension().is_none_or(|ext| ext != "txt") || !path.with_extension("json").exists()
+    });
+    files.sort();
+    Ok(files)
+}
+
+// Read a stats file of either kind, and check that it adds up to counters of the current
+// layout, so that a file that doesn't is skipped whole rather than added in part.
+fn read_stats_file(path: &Path) -> Result<StatsFile, Box<dyn Error>> {
+    let stats = if path.extension().is_some_and(|ext| ext == "json") {
+        StatsFile::read(path)?
+    } else {
+        read_legacy(path)?
+    };
+    stats.add_to(
+        &new_counter_tree(),
+        &bursts_counter(),
+        &mut Groups::new(),
+        &mut Groups::new(),
+        &Unparsed::new(),
+        &RawCounts::new(),
+    )?;
+    Ok(stats)
+}
+
+/// The summed stats of all stats files in the current directory.
+pub struct Aggregate {
+    pub files: usize,
+    pub counters: Counter,
+    /// Children-changed bursts, see `ChildrenBursts`.
+    pub bursts: Counter,
+    pub applications: Groups,
+    pub toolkits: Groups,
+    pub unparsed: Unparsed,
+    pub raw: RawCounts,
+    /// The parsed counts of only the sessions that also tallied raw signals, to compare with.
+    pub raw_parsed: Counter,
+}
+
+/// Read and sum all stats files in the current directory.
+/// Files that can't be read are reported and skipped.
+pub fn read_stats_files() -> Result<Aggregate, Box<dyn Error>> {
+    let mut aggregate = Aggregate {
+        files: 0,
+        counters: new_counter_tree(),
+        bursts: bursts_counter(),
+        applications: Groups::new(),
+        toolkits: Groups::new(),
+        unparsed: Unparsed::new(),
+        raw: RawCounts::new(),
+        raw_parsed: new_counter_tree(),
+    };
+
+    let mut files = 0;
+    for file in stats_files()? {
+        let stats = match read_stats_file(&file) {
+            Ok(stats) => stats,
+            Err(e) => {
+                eprintln!("{}: {e}, skipped", file.display());
+                continue;
+            }
+        };
+        files += 1;
+        stats.add_to(
+            &aggregate.counters,
+            &aggregate.bursts,
+            &mut aggregate.applications,
+            &mut aggregate.toolkits,
+            &aggregate.unparsed,
+            &aggregate.raw,
+        )?;
+        if !stats.raw.is_empty() {
+            stats.counters.add_to(&aggregate.raw_parsed)?;
+        }
+    }
+
+    Ok(Aggregate { files, ..aggregate })
+}
+
+/// Read all stats files in the current directory and print the summed stats.
+pub fn aggregate() -> Result<(), Box<dyn Error>> {
+    let aggregate = read_stats_files()?;
+
+    println!("Files read: {}", aggregate.files);
+    print_stats(&aggregate.counters, &aggregate.bursts, &aggregate.unparsed);
+    if !aggregate.applications.is_empty() {
+        print_groups(&aggregate.applications, &aggregate.toolkits);
+    }
+    if !aggregate.raw.is_empty() {
+        print_raw_stats(&aggregate.raw, &aggregate.raw_parsed);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,257 @@
+use atspi::proxy::{accessible::AccessibleProxy, application::ApplicationProxy};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use zbus::{Connection, fdo::DBusProxy, names::BusName, proxy::CacheProperties};
+
+use crate::counters::{Counter, CounterStats, Getters, new_counter_tree};
+
+/// The root accessible of every application, which carries the application's name and
+/// implements the Application interface.
+const ROOT_PATH: &str = "/org/a11y/atspi/accessible/root";
+
+/// How long to wait for an application, or the bus daemon, to answer a lookup.
+pub const RESOLVE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The toolkit of applications that don't tell theirs.
+const UNKNOWN_TOOLKIT: &str = "unknown";
+
+/// What an application told about itself.
+#[derive(Debug, Clone, Default)]
+struct AppInfo {
+    /// The name of its root accessible, e.g. "Firefox".
+    name: Option<String>,
+    /// Its toolkit name and version, e.g. "GTK 3.24.41".
+    toolkit: Option<String>,
+    /// The executable name of the process on the other end of its connection, e.g. "firefox",
+    /// as the bus daemon and `/proc` tell.
+    process: Option<String>,
+}
+
+impl AppInfo {
+    // The name of the application in reports: the name of its root accessible, or else that of
+    // its process, or else `sender`.
+    fn display_name(&self, sender: &str) -> String {
+        self.name
+            .clone()
+            .or_else(|| self.process.clone())
+            .unwrap_or(sender.to_string())
+    }
+}
+
+type Infos = Arc<Mutex<HashMap<String, AppInfo>>>;
+
+/// The events of applications, or of toolkits, by name. Each is a tree made by
+/// `new_counter_tree`.
+pub type Groups = BTreeMap<String, Counter>;
+
+/// The events of each application, by the unique bus name of the sender.
+///
+/// Given a connection, each sender is asked for its name and toolkit in the background, once,
+/// when it first appears, and the bus daemon for its process. Senders that left the bus before
+/// they were looked up are listed by their unique name.
+#[derive(Default)]
+pub struct Apps {
+    senders: HashMap<String, Counter>,
+    infos: Infos,
+    connection: Option<Connection>,
+}
+
+impl Apps {
+    pub fn new() -> Self {
+        Apps::default()
+    }
+
+    /// Look up the names, toolkits and processes of new senders on `connection`.
+    pub fn resolve_on(&mut self, connection: Connection) {
+        self.connection = Some(connection);
+    }
+
+    /// The counter tree of the events of `sender`, made by `new_counter_tree`.
+    pub fn counters(&mut self, sender: &str) -> &Counter {
+        if !self.senders.contains_key(sender) {
+            if let Some(connection) = &self.connection {
+                tokio::spawn(resolve(
+                    connection.clone(),
+                    sender.to_string(),
+                    Arc::clone(&self.infos),
+                ));
+            }
+            self.senders.insert(sender.to_string(), new_counter_tree());
+        }
+        &self.senders[sender]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.senders.is_empty()
+    }
+
+    // The events of the senders added together by the group `key` puts them in.
+    fn groups(&self, key: impl Fn(&str, &AppInfo) -> String) -> Groups {
+        let infos = self.infos.lock().unwrap_or_else(|e| e.into_inner());
+        let mut groups = Groups::new();
+
+        for (sender, counters) in &self.senders {
+            let name = key(sender, &infos.get(sender).cloned().unwrap_or_default());
+            groups
+                .entry(name)
+                .or_insert_with(new_counter_tree)
+                .merge(counters);
+        }
+
+        groups
+    }
+
+    /// The events of each application. Senders with the same name, like an application that
+    /// was restarted, are added together.
+    pub fn applications(&self) -> Groups {
+        self.groups(|sender, info| info.display_name(sender))
+    }
+
+    /// The events of each toolkit.
+    pub fn toolkits(&self) -> Groups {
+        self.groups(|_, info| info.toolkit.clone().unwrap_or(UNKNOWN_TOOLKIT.to_string()))
+    }
+
+    pub fn print(&self) {
+        if !self.is_empty() {
+            print_groups(&self.applications(), &self.toolkits());
+        }
+    }
+}
+
+/// Print the events of each application by interface, then of each toolkit by interface
+/// and member.
+pub fn print_groups(applications: &Groups, toolkits: &Groups) {
+    println!();
+    println!("Application stats:");
+    print_table("application", &busiest_first(applications));
+
+    let toolkits = busiest_first(toolkits);
+    println!();
+    println!("Toolkit stats:");
+    print_table("toolkit", &toolkits);
+    for (toolkit, counters) in &toolkits {
+        println!();
+        println!("{toolkit} events:");
+        counters.pretty_print_active();
+    }
+}
+
+fn busiest_first(groups: &Groups) -> Vec<(&String, &Counter)> {
+    let mut groups: Vec<(&String, &Counter)> = groups.iter().collect();
+    groups.sort_by_key(|(name, counters)| (std::cmp::Reverse(counters.total()), *name));
+    groups
+}
+
+// Print one row of interface totals per group, leaving out interfaces without events.
+fn print_table(heading: &str, groups: &[(&String, &Counter)]) {
+    let Some((_, first)) = groups.first() else {
+        return;
+    };
+    let interfaces: Vec<(usize, &str)> = first
+        .get_children()
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| {
+            groups
+                .iter()
+                .any(|(_, counters)| counters.get_children()[*i].total() > 0)
+        })
+        .map(|(i, iface)| (i, iface.name()))
+        .collect();
+
+    print!("{:<30}", heading);
+    for (_, name) in &interfaces {
+        print!("{:<10} ", name);
+    }
+    println!("total");
+    for (name, counters) in groups {
+        print!("{:<30}", name);
+        for (i, _) in &interfaces {
+            print!("{:<10} ", counters.get_children()[*i].total());
+        }
+        println!("{}", counters.total());
+    }
+}
+
+async fn resolve(connection: Connection, sender: String, infos: Infos) {
+    let name = tokio::time::timeout(RESOLVE_TIMEOUT, app_name(&connection, &sender)).await;
+    let toolkit = tokio::time::timeout(RESOLVE_TIMEOUT, toolkit(&connection, &sender)).await;
+    let pid = tokio::time::timeout(RESOLVE_TIMEOUT, process_id(&connection, &sender)).await;
+    let info = AppInfo {
+        name: name
+            .ok()
+            .and_then(Result::ok)
+            .filter(|name| !name.is_empty()),
+        toolkit: toolkit
+            .ok()
+            .and_then(Result::ok)
+            .filter(|name| !name.is_empty()),
+        process: pid.ok().and_then(Result::ok).and_then(process_name),
+    };
+
+    infos
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(sender, info);
+}
+
+// The name of the application's root accessible.
+async fn app_name(connection: &Connection, sender: &str) -> zbus::Result<String> {
+    AccessibleProxy::builder(connection)
+        .destination(sender)?
+        .path(ROOT_PATH)?
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await?
+        .name()
+        .await
+}
+
+// The toolkit name of the application, with its version if it has one.
+async fn toolkit(connection: &Connection, sender: &str) -> zbus::Result<String> {
+    let application = ApplicationProxy::builder(connection)
+        .destination(sender)?
+        .path(ROOT_PATH)?
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await?;
+    let name = application.toolkit_name().await?;
+    let version = application.version().await.unwrap_or_default();
+
+    Ok(if version.is_empty() {
+        name
+    } else {
+        format!("{name} {version}")
+    })
+}
+
+/// The process ID of the connection of `sender`, as the bus daemon has it.
+pub async fn process_id(connection: &Connection, sender: &str) -> zbus::Result<u32> {
+    let sender = BusName::try_from(sender)?;
+    let pid = DBusProxy::new(connection)
+        .await?
+        .get_connection_unix_process_id(sender)
+        .await?;
+    Ok(pid)
+}
+
+// The name of the executable of process `pid`, or its command name if the executable
+// can't be read, as for processes of other users.
+fn process_name(pid: u32) -> Option<String> {
+    let proc = Path::new("/proc").join(pid.to_string());
+    let exe = std::fs::read_link(proc.join("exe")).ok().and_then(|exe| {
+        let name = exe.file_name()?.to_str()?;
+        Some(name.strip_suffix(" (deleted)").unwrap_or(name).to_string())
+    });
+
+    exe.or_else(|| {
+        let comm = std::fs::read_to_string(proc.join("comm")).ok()?;
+        Some(comm.trim_end().to_string())
+    })
+    .filter(|name| !name.is_empty())
+}
@@ -0,0 +1,104 @@
+use atspi::events::{
+    CacheEvents, Event, FocusEvents, MouseEvents, ObjectEvents, WindowEvents, cache, focus, mouse,
+    object, window,
+};
+use std::{hint::black_box, time::Instant};
+
+use crate::categories::{EventCategories, interface_name};
+use crate::counters::{CounterStats, new_counter_tree};
+use crate::matchers::match_event;
+
+/// Number of events counted per run.
+const EVENTS: usize = 10_000_000;
+
+// A mix of the events that dominate real sessions: mouse motion and object updates,
+// with a few rarer ones from the tail of atspi's match order.
+fn sample_events() -> Vec<Event> {
+    vec![
+        Event::Mouse(MouseEvents::Abs(mouse::AbsEvent::default())),
+        Event::Mouse(MouseEvents::Rel(mouse::RelEvent::default())),
+        Event::Object(ObjectEvents::BoundsChanged(
+            object::BoundsChangedEvent::default(),
+        )),
+        Event::Object(ObjectEvents::StateChanged(
+            object::StateChangedEvent::default(),
+        )),
+        Event::Object(ObjectEvents::TextCaretMoved(
+            object::TextCaretMovedEvent::default(),
+        )),
+        Event::Object(ObjectEvents::ChildrenChanged(
+            object::ChildrenChangedEvent::default(),
+        )),
+        Event::Window(WindowEvents::Activate(window::ActivateEvent::default())),
+        Event::Cache(CacheEvents::Remove(cache::RemoveAccessibleEvent::default())),
+        Event::Focus(FocusEvents::Focus(focus::FocusEvent::default())),
+    ]
+}
+
+// The path of an event in the counter tree, as the positions `increment_index` takes and as
+// the names `increment` looks up.
+fn paths(ev: &Event) -> ((usize, usize), [String; 2]) {
+    match ev {
+        Event::Object(ev) => path(ev),
+        Event::Window(ev) => path(ev),
+        Event::Mouse(ev) => path(ev),
+        Event::Cache(ev) => path(ev),
+        Event::Focus(ev) => path(ev),
+        _ => unreachable!("not in the sample"),
+    }
+}
+
+fn path<T: EventCategories>(ev: &T) -> ((usize, usize), [String; 2]) {
+    let category = T::CATEGORIES[ev.index()];
+    (
+        (T::INTERFACE, ev.index()),
+        [interface_name(category.interface), category.name()],
+    )
+}
+
+fn report(label: &str, count: u64, elapsed: std::time::Duration) {
+    println!(
+        "{label:<30}{count:<12}{:>8.2} ns/event",
+        elapsed.as_nanos() as f64 / EVENTS as f64
+    );
+}
+
+/// Measure the cost of counting one event on the hot path, as `match_event` does it with the
+/// details of its event, then of counting the same events by position and by name alone.
+/// Run with `cargo test --release -- --ignored bench`.
+#[test]
+#[ignore = "takes seconds; run in release mode to measure"]
+fn bench() {
+    let events = sample_events();
+    println!(
+        "Counting {EVENTS} events, cycling through {} kinds",
+        events.len()
+    );
+
+    let counters = new_counter_tree();
+    let start = Instant::now();
+    for ev in events.iter().cycle().take(EVENTS) {
+        match_event(black_box(ev), &counters);
+    }
+    report("match_event", counters.total(), start.elapsed());
+
+    let (indices, names): (Vec<(usize, usize)>, Vec<[String; 2]>) =
+        events.iter().map(paths).unzip();
+    let counters = new_counter_tree();
+    let start = Instant::now();
+    for (iface, index) in indices.iter().cycle().take(EVENTS) {
+        counters.increment_index(black_box(*iface), black_box(*index));
+    }
+    report(
+        "by index (increment_index)",
+        counters.total(),
+        start.elapsed(),
+    );
+
+    let counters = new_counter_tree();
+    let start = Instant::now();
+    for [iface, name] in names.iter().cycle().take(EVENTS) {
+        counters.increment(black_box(&[iface.as_str(), name.as_str()]));
+    }
+    report("by name (increment)", counters.total(), start.elapsed());
+}
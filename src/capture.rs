@@ -0,0 +1,416 @@
+use chrono::{DateTime, Local};
+use std::{
+    error::Error,
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+    time::Instant,
+};
+use zbus::{
+    Message,
+    message::Type,
+    zvariant::{Endian, Structure, serialized::Context, serialized::Data},
+};
+
+// A capture file starts with a header:
+//
+// magic           8 bytes, "ATSPICAP"
+// version         u16
+// start           i64, microseconds since the Unix epoch
+// tool version    string
+//
+// followed by one record per signal, in the order they were received:
+//
+// offset          u64, microseconds since the start
+// sender          string
+// path            string
+// interface       string
+// member          string
+// signature       string, the body signature
+// endian          u8, 'l' or 'B', of the body
+// body            u32 length, then the body as it was on the bus
+//
+// Integers are little-endian, strings are a u16 length followed by UTF-8.
+// Records are only ever appended, so a capture cut short ends in at most one partial record.
+
+const MAGIC: &[u8; 8] = b"ATSPICAP";
+
+/// The largest body a record can have: D-Bus messages are at most 128 MiB.
+/// A larger length in a file is corrupt, and is not allocated.
+const MAX_BODY_LEN: u32 = 128 * 1024 * 1024;
+
+/// Version of the capture file layout.
+/// Bump this whenever a change to the layout would break older readers.
+pub const CAPTURE_VERSION: u16 = 1;
+
+/// One signal in a capture file.
+#[derive(Debug, Clone)]
+pub struct Record {
+    /// Microseconds since the start of the capture.
+    pub offset: u64,
+    pub sender: String,
+    pub path: String,
+    pub interface: String,
+    pub member: String,
+    pub signature: String,
+    pub endian: Endian,
+    pub body: Vec<u8>,
+}
+
+impl Record {
+    /// The record of `msg`, if it is a signal.
+    pub fn from_message(msg: &Message, offset: u64) -> Option<Self> {
+        let header = msg.header();
+        if header.message_type() != Type::Signal {
+            return None;
+        }
+
+        let body = msg.body();
+        Some(Record {
+            offset,
+            sender: header.sender().map_or("", |name| name.as_str()).to_string(),
+            path: header.path()?.as_str().to_string(),
+            interface: header.interface()?.as_str().to_string(),
+            member: header.member()?.as_str().to_string(),
+            signature: body.signature().to_string_no_parens(),
+            endian: body.data().context().endian(),
+            body: body.data().bytes().to_vec(),
+        })
+    }
+
+    /// Rebuild the signal of this record.
+    pub fn to_message(&self) -> zbus::Result<Message> {
+        let mut builder = Message::signal(
+            self.path.as_str(),
+            self.interface.as_str(),
+            self.member.as_str(),
+        )?
+        .endian(self.endian);
+        if !self.sender.is_empty() {
+            builder = builder.sender(self.sender.as_str())?;
+        }
+
+        if self.signature.is_empty() {
+            if !self.body.is_empty() {
+                return Err(zbus::Error::Failure(
+                    "a body without a signature".to_string(),
+                ));
+            }
+            return builder.build(&());
+        }
+
+        // The body is read back by its signature and serialized again, so a body that doesn't
+        // match its signature is an error here rather than a malformed message.
+        let data = Data::new(self.body.as_slice(), Context::new_dbus(self.endian, 0));
+        let (fields, len) =
+            data.deserialize_for_dynamic_signature::<_, Structure>(self.signature.as_str())?;
+        if len != self.body.len() {
+            return Err(zbus::Error::Failure(format!(
+                "{} bytes after a body of signature {}",
+                self.body.len() - len,
+                self.signature
+            )));
+        }
+        builder.build(&fields)
+    }
+}
+
+fn write_string(out: &mut impl Write, s: &str) -> std::io::Result<()> {
+    let len = u16::try_from(s.len()).map_err(std::io::Error::other)?;
+    out.write_all(&len.to_le_bytes())?;
+    out.write_all(s.as_bytes())
+}
+
+/// Appends the signals of a session to a new capture file.
+pub struct CaptureWriter {
+    out: BufWriter<File>,
+    start: Instant,
+}
+
+impl CaptureWriter {
+    /// Create the capture file at `path` and write its header.
+    /// An existing file is never overwritten.
+    pub fn create(path: &Path, start: DateTime<Local>) -> Result<Self, Box<dyn Error>> {
+        let file = OpenOptions::new()
+            .append(true)
+            .create_new(true)
+            .open(path)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        let mut out = BufWriter::new(file);
+
+        out.write_all(MAGIC)?;
+        out.write_all(&CAPTURE_VERSION.to_le_bytes())?;
+        out.write_all(&start.timestamp_micros().to_le_bytes())?;
+        write_string(&mut out, env!("CARGO_PKG_VERSION"))?;
+
+        Ok(CaptureWriter {
+            out,
+            start: Instant::now(),
+        })
+    }
+
+    /// Append `msg` to the capture, if it is a signal.
+    pub fn write_message(&mut self, msg: &Message) -> Result<(), Box<dyn Error>> {
+        let offset = u64::try_from(self.start.elapsed().as_micros()).unwrap_or(u64::MAX);
+        match Record::from_message(msg, offset) {
+            Some(record) => self.write(&record),
+            None => Ok(()),
+        }
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<(), Box<dyn Error>> {
+        let out = &mut self.out;
+        out.write_all(&record.offset.to_le_bytes())?;
+        write_string(out, &record.sender)?;
+        write_string(out, &record.path)?;
+        write_string(out, &record.interface)?;
+        write_string(out, &record.member)?;
+        write_string(out, &record.signature)?;
+        out.write_all(&[match record.endian {
+            Endian::Little => b'l',
+            Endian::Big => b'B',
+        }])?;
+        let len = u32::try_from(record.body.len())?;
+        out.write_all(&len.to_le_bytes())?;
+        out.write_all(&record.body)?;
+        Ok(())
+    }
+
+    /// Write out what is still buffered.
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+fn read_array<const N: usize>(input: &mut impl Read) -> std::io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_string(input: &mut impl Read) -> Result<String, Box<dyn Error>> {
+    let len = u16::from_le_bytes(read_array(input)?);
+    let mut bytes = vec![0; len.into()];
+    input.read_exact(&mut bytes)?;
+    Ok(String::from_utf8(bytes)?)
+}
+
+/// Reads the records of a capture file, in order.
+pub struct CaptureReader {
+    input: BufReader<File>,
+    /// When the capture started.
+    pub start: DateTime<Local>,
+    /// The version of atspi-counters that wrote the capture.
+    pub tool_version: String,
+}
+
+impl CaptureReader {
+    /// Open the capture file at `path` and read its header.
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = File::open(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let mut input = BufReader::new(file);
+
+        let magic: [u8; 8] = read_array(&mut input)?;
+        if &magic != MAGIC {
+            return Err(format!("{}: not a capture file", path.display()).into());
+        }
+        let version = u16::from_le_bytes(read_array(&mut input)?);
+        if version != CAPTURE_VERSION {
+            return Err(format!(
+                "{}: capture version {version} is not supported, expected {CAPTURE_VERSION}",
+                path.display()
+            )
+            .into());
+        }
+        let start = i64::from_le_bytes(read_array(&mut input)?);
+        let start = DateTime::from_timestamp_micros(start)
+            .ok_or_else(|| format!("{}: invalid start time", path.display()))?
+            .into();
+        let tool_version = read_string(&mut input)?;
+
+        Ok(CaptureReader {
+            input,
+            start,
+            tool_version,
+        })
+    }
+
+    /// The next record, or `None` at the end of the file.
+    pub fn next_record(&mut self) -> Result<Option<Record>, Box<dyn Error>> {
+        let offset = match read_array(&mut self.input) {
+            Ok(bytes) => u64::from_le_bytes(bytes),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let input = &mut self.input;
+        let sender = read_string(input)?;
+        let path = read_string(input)?;
+        let interface = read_string(input)?;
+        let member = read_string(input)?;
+        let signature = read_string(input)?;
+        let endian = match read_array(input)? {
+            [b'l'] => Endian::Little,
+            [b'B'] => Endian::Big,
+            [other] => return Err(format!("invalid endianness {other:#x}").into()),
+        };
+        let len = u32::from_le_bytes(read_array(input)?);
+        if len > MAX_BODY_LEN {
+            return Err(format!("body length {len} is over the D-Bus maximum").into());
+        }
+        let mut body = vec![0; usize::try_from(len)?];
+        input.read_exact(&mut body)?;
+
+        Ok(Some(Record {
+            offset,
+            sender,
+            path,
+            interface,
+            member,
+            signature,
+            endian,
+            body,
+        }))
+    }
+}
+
+impl Iterator for CaptureReader {
+    type Item = Result<Record, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(offset: u64, member: &str) -> Record {
+        let msg = Message::signal(
+            "/org/a11y/atspi/accessible/1",
+            "org.a11y.atspi.Event.Object",
+            member,
+        )
+        .unwrap()
+        .build(&("focused", 1i32, 0i32))
+        .unwrap();
+        Record::from_message(&msg, offset).unwrap()
+    }
+
+    // A capture file of its own for each test, removed when done.
+    struct TempCapture(std::path::PathBuf);
+
+    impl TempCapture {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "atspi-counters-{name}-{}.atspi",
+                std::process::id()
+            ));
+            let _ = std::fs::remove_file(&path);
+            TempCapture(path)
+        }
+    }
+
+    impl Drop for TempCapture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn write_capture(path: &Path, records: &[Record]) -> DateTime<Local> {
+        let start = DateTime::from_timestamp_micros(1_700_000_000_123_456)
+            .unwrap()
+            .into();
+        let mut writer = CaptureWriter::create(path, start).unwrap();
+        for record in records {
+            writer.write(record).unwrap();
+        }
+        writer.flush().unwrap();
+        start
+    }
+
+    #[test]
+    fn records_read_back_as_written() {
+        let capture = TempCapture::new("round-trip");
+        let written = [record(0, "StateChanged"), record(1_500, "TextChanged")];
+        let start = write_capture(&capture.0, &written);
+
+        let reader = CaptureReader::open(&capture.0).unwrap();
+        assert_eq!(reader.start, start);
+        assert_eq!(reader.tool_version, env!("CARGO_PKG_VERSION"));
+        let read: Vec<Record> = reader.map(Result::unwrap).collect();
+        assert_eq!(read.len(), written.len());
+        for (read, written) in read.iter().zip(&written) {
+            assert_eq!(read.offset, written.offset);
+            assert_eq!(read.sender, written.sender);
+            assert_eq!(read.path, written.path);
+            assert_eq!(read.interface, written.interface);
+            assert_eq!(read.member, written.member);
+            assert_eq!(read.signature, written.signature);
+            assert_eq!(read.endian, written.endian);
+            assert_eq!(read.body, written.body);
+        }
+        let msg = read[0].to_message().unwrap();
+        assert_eq!(
+            msg.body().deserialize::<(String, i32, i32)>().unwrap().0,
+            "focused"
+        );
+        let rebuilt = Record::from_message(&msg, 0).unwrap();
+        assert_eq!(rebuilt.signature, written[0].signature);
+        assert_eq!(rebuilt.body, written[0].body);
+    }
+
+    #[test]
+    fn body_not_matching_its_signature_is_rejected() {
+        let mut short = record(0, "StateChanged");
+        short.body.truncate(short.body.len() - 2);
+        assert!(short.to_message().is_err());
+
+        let mut long = record(0, "StateChanged");
+        long.body.extend_from_slice(&[0; 4]);
+        assert!(long.to_message().is_err());
+
+        let mut other = record(0, "StateChanged");
+        other.signature = "ss".to_string();
+        assert!(other.to_message().is_err());
+    }
+
+    #[test]
+    fn truncated_record_ends_the_capture_with_an_error() {
+        let capture = TempCapture::new("truncated");
+        write_capture(
+            &capture.0,
+            &[record(0, "StateChanged"), record(1, "TextChanged")],
+        );
+        let len = std::fs::metadata(&capture.0).unwrap().len();
+        let file = OpenOptions::new().write(true).open(&capture.0).unwrap();
+        file.set_len(len - 3).unwrap();
+
+        let mut reader = CaptureReader::open(&capture.0).unwrap();
+        assert_eq!(
+            reader.next_record().unwrap().unwrap().member,
+            "StateChanged"
+        );
+        assert!(reader.next_record().is_err());
+    }
+
+    #[test]
+    fn oversized_body_is_rejected() {
+        let capture = TempCapture::new("oversized");
+        write_capture(&capture.0, &[record(0, "StateChanged")]);
+        let mut bytes = std::fs::read(&capture.0).unwrap();
+        let body_len = record(0, "StateChanged").body.len();
+        let at = bytes.len() - body_len - 4;
+        bytes[at..at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&capture.0, &bytes).unwrap();
+
+        let mut reader = CaptureReader::open(&capture.0).unwrap();
+        let error = reader.next_record().unwrap_err();
+        assert!(
+            error.to_string().contains("over the D-Bus maximum"),
+            "{error}"
+        );
+    }
+}
@@ -0,0 +1,378 @@
+use atspi::events::{
+    AvailableEvent, BusProperties, CacheEvents, DocumentEvents, EventListenerDeregisteredEvent,
+    EventListenerEvents, EventListenerRegisteredEvent, EventTypeProperties, FocusEvents,
+    KeyboardEvents, MessageConversion, MouseEvents, ObjectEvents, TerminalEvents, WindowEvents,
+    cache, document, focus, keyboard, mouse, object, terminal, window,
+};
+use zbus::zvariant::{Signature, Type};
+
+/// An event category, as atspi describes the event type: the D-Bus interface, member and
+/// body signature of the signal, the name of the atspi type it is parsed into and the variant
+/// of the interface's event enum that holds it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Category {
+    pub interface: &'static str,
+    pub member: &'static str,
+    pub signature: &'static Signature,
+    pub event_type: &'static str,
+    pub variant: &'static str,
+}
+
+impl Category {
+    /// The name of this category in reports and stats files, e.g. "state-changed".
+    ///
+    /// This is the member in kebab-case, unless another category of the interface shares the
+    /// member (like the two `AddAccessible` signals of the cache), in which case it is the
+    /// atspi type name instead.
+    pub fn name(&self) -> String {
+        let siblings = INTERFACES
+            .iter()
+            .find(|categories| categories[0].interface == self.interface)
+            .map_or(&[][..], |categories| *categories);
+        let shared = siblings
+            .iter()
+            .any(|other| other != self && other.member == self.member);
+
+        if shared {
+            let event_type = self.event_type.rsplit("::").next().unwrap_or_default();
+            kebab_case(event_type.strip_suffix("Event").unwrap_or(event_type))
+        } else {
+            kebab_case(self.member)
+        }
+    }
+}
+
+/// The category of a signal by its D-Bus header, with its position in `INTERFACES`.
+///
+/// The body signature only decides between categories that share a member, since toolkits
+/// may send the same event with a different body (e.g. Qt's).
+pub fn find_category(
+    interface: &str,
+    member: &str,
+    signature: &str,
+) -> Option<(usize, usize, Category)> {
+    let iface = INTERFACES
+        .iter()
+        .position(|categories| categories[0].interface == interface)?;
+    let candidates: Vec<(usize, &Category)> = INTERFACES[iface]
+        .iter()
+        .enumerate()
+        .filter(|(_, cat)| cat.member == member)
+        .collect();
+
+    let (index, category) = match candidates[..] {
+        [single] => single,
+        _ => {
+            let signature: Signature = signature.parse().ok()?;
+            *candidates
+                .iter()
+                .find(|(_, cat)| *cat.signature == signature)?
+        }
+    };
+    Some((iface, index, *category))
+}
+
+/// The name of a D-Bus interface in reports and stats files, e.g. "object" for
+/// "org.a11y.atspi.Event.Object".
+pub fn interface_name(interface: &str) -> String {
+    interface
+        .rsplit('.')
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+// "StateChanged" -> "state-changed"
+fn kebab_case(name: &str) -> String {
+    let mut kebab = String::with_capacity(name.len() + 4);
+    let mut prev_lowercase = false;
+
+    for c in name.chars() {
+        if c.is_uppercase() && prev_lowercase {
+            kebab.push('-');
+        }
+        prev_lowercase = c.is_lowercase() || c.is_ascii_digit();
+        kebab.extend(c.to_lowercase());
+    }
+
+    kebab
+}
+
+/// The interfaces, in the order of the counter tree.
+enum InterfaceOrder {
+    Object,
+    Window,
+    Document,
+    Terminal,
+    Mouse,
+    Keyboard,
+    Registry,
+    Cache,
+    Focus,
+    Socket,
+}
+
+/// Maps the events of an atspi interface to their categories.
+pub trait EventCategories {
+    /// The position of this interface in `INTERFACES`.
+    const INTERFACE: usize;
+    /// The categories of all events of this interface, in atspi's order.
+    const CATEGORIES: &'static [Category];
+
+    /// The position of the category of this event in `CATEGORIES`.
+    fn index(&self) -> usize;
+}
+
+// Implement `EventCategories` for an atspi event enum, with one category per variant.
+//
+// The match in `index` is exhaustive, so a variant added to or removed from atspi
+// fails to compile here rather than going uncounted. The categories themselves are
+// taken from each event type's `BusProperties`.
+macro_rules! event_categories {
+    ($iface:ident => $enum:ident { $($variant:ident($event:ty)),* $(,)? }) => {
+        impl EventCategories for $enum {
+            const INTERFACE: usize = InterfaceOrder::$iface as usize;
+            const CATEGORIES: &'static [Category] = &[$(
+                Category {
+                    interface: <$event as BusProperties>::DBUS_INTERFACE,
+                    member: <$event as BusProperties>::DBUS_MEMBER,
+                    signature: <<$event as MessageConversion>::Body as Type>::SIGNATURE,
+                    event_type: stringify!($event),
+                    variant: stringify!($variant),
+                },
+            )*];
+
+            fn index(&self) -> usize {
+                // Numbers the variants in the order of `CATEGORIES`.
+                #[allow(clippy::enum_variant_names)]
+                enum Index {
+                    $($variant,)*
+                }
+
+                let index = match self {
+                    $($enum::$variant(_) => Index::$variant as usize,)*
+                };
+                debug_assert_eq!(Self::CATEGORIES[index].member, self.member());
+                index
+            }
+        }
+    };
+}
+
+event_categories!(Object => ObjectEvents {
+    PropertyChange(object::PropertyChangeEvent),
+    BoundsChanged(object::BoundsChangedEvent),
+    LinkSelected(object::LinkSelectedEvent),
+    StateChanged(object::StateChangedEvent),
+    ChildrenChanged(object::ChildrenChangedEvent),
+    VisibleDataChanged(object::VisibleDataChangedEvent),
+    SelectionChanged(object::SelectionChangedEvent),
+    ModelChanged(object::ModelChangedEvent),
+    ActiveDescendantChanged(object::ActiveDescendantChangedEvent),
+    Announcement(object::AnnouncementEvent),
+    AttributesChanged(object::AttributesChangedEvent),
+    RowInserted(object::RowInsertedEvent),
+    RowReordered(object::RowReorderedEvent),
+    RowDeleted(object::RowDeletedEvent),
+    ColumnInserted(object::ColumnInsertedEvent),
+    ColumnReordered(object::ColumnReorderedEvent),
+    ColumnDeleted(object::ColumnDeletedEvent),
+    TextBoundsChanged(object::TextBoundsChangedEvent),
+    TextSelectionChanged(object::TextSelectionChangedEvent),
+    TextChanged(object::TextChangedEvent),
+    TextAttributesChanged(object::TextAttributesChangedEvent),
+    TextCaretMoved(object::TextCaretMovedEvent),
+});
+
+event_categories!(Window => WindowEvents {
+    PropertyChange(window::PropertyChangeEvent),
+    Minimize(window::MinimizeEvent),
+    Maximize(window::MaximizeEvent),
+    Restore(window::RestoreEvent),
+    Close(window::CloseEvent),
+    Create(window::CreateEvent),
+    Reparent(window::ReparentEvent),
+    DesktopCreate(window::DesktopCreateEvent),
+    DesktopDestroy(window::DesktopDestroyEvent),
+    Destroy(window::DestroyEvent),
+    Activate(window::ActivateEvent),
+    Deactivate(window::DeactivateEvent),
+    Raise(window::RaiseEvent),
+    Lower(window::LowerEvent),
+    Move(window::MoveEvent),
+    Resize(window::ResizeEvent),
+    Shade(window::ShadeEvent),
+    UUshade(window::UUshadeEvent),
+    Restyle(window::RestyleEvent),
+});
+
+event_categories!(Document => DocumentEvents {
+    LoadComplete(document::LoadCompleteEvent),
+    Reload(document::ReloadEvent),
+    LoadStopped(document::LoadStoppedEvent),
+    ContentChanged(document::ContentChangedEvent),
+    AttributesChanged(document::AttributesChangedEvent),
+    PageChanged(document::PageChangedEvent),
+});
+
+event_categories!(Terminal => TerminalEvents {
+    LineChanged(terminal::LineChangedEvent),
+    ColumnCountChanged(terminal::ColumnCountChangedEvent),
+    LineCountChanged(terminal::LineCountChangedEvent),
+    ApplicationChanged(terminal::ApplicationChangedEvent),
+    CharWidthChanged(terminal::CharWidthChangedEvent),
+});
+
+event_categories!(Mouse => MouseEvents {
+    Abs(mouse::AbsEvent),
+    Rel(mouse::RelEvent),
+    Button(mouse::ButtonEvent),
+});
+
+event_categories!(Keyboard => KeyboardEvents {
+    Modifiers(keyboard::ModifiersEvent),
+});
+
+event_categories!(Registry => EventListenerEvents {
+    Registered(EventListenerRegisteredEvent),
+    Deregistered(EventListenerDeregisteredEvent),
+});
+
+event_categories!(Cache => CacheEvents {
+    Add(cache::AddAccessibleEvent),
+    LegacyAdd(cache::LegacyAddAccessibleEvent),
+    Remove(cache::RemoveAccessibleEvent),
+});
+
+event_categories!(Focus => FocusEvents {
+    Focus(focus::FocusEvent),
+});
+
+// `AvailableEvent` is the only event of its interface and has no enum of its own.
+impl EventCategories for AvailableEvent {
+    const INTERFACE: usize = InterfaceOrder::Socket as usize;
+    const CATEGORIES: &'static [Category] = &[Category {
+        interface: AvailableEvent::DBUS_INTERFACE,
+        member: AvailableEvent::DBUS_MEMBER,
+        signature: <<AvailableEvent as MessageConversion>::Body as Type>::SIGNATURE,
+        event_type: "AvailableEvent",
+        variant: "Available",
+    }];
+
+    fn index(&self) -> usize {
+        0
+    }
+}
+
+/// The categories of every interface, in the order of the counter tree.
+pub const INTERFACES: [&[Category]; 10] = {
+    let mut interfaces: [&[Category]; 10] = [&[]; 10];
+    interfaces[ObjectEvents::INTERFACE] = ObjectEvents::CATEGORIES;
+    interfaces[WindowEvents::INTERFACE] = WindowEvents::CATEGORIES;
+    interfaces[DocumentEvents::INTERFACE] = DocumentEvents::CATEGORIES;
+    interfaces[TerminalEvents::INTERFACE] = TerminalEvents::CATEGORIES;
+    interfaces[MouseEvents::INTERFACE] = MouseEvents::CATEGORIES;
+    interfaces[KeyboardEvents::INTERFACE] = KeyboardEvents::CATEGORIES;
+    interfaces[EventListenerEvents::INTERFACE] = EventListenerEvents::CATEGORIES;
+    interfaces[CacheEvents::INTERFACE] = CacheEvents::CATEGORIES;
+    interfaces[FocusEvents::INTERFACE] = FocusEvents::CATEGORIES;
+    interfaces[AvailableEvent::INTERFACE] = AvailableEvent::CATEGORIES;
+
+    let mut i = 0;
+    while i < interfaces.len() {
+        assert!(!interfaces[i].is_empty(), "every interface has categories");
+        i += 1;
+    }
+    interfaces
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn categories_are_found_by_their_header() {
+        for (iface, categories) in INTERFACES.iter().enumerate() {
+            for (index, category) in categories.iter().enumerate() {
+                let signature = category.signature.to_string();
+                let found = find_category(category.interface, category.member, &signature);
+                assert_eq!(
+                    found,
+                    Some((iface, index, *category)),
+                    "{}:{} ({signature})",
+                    category.interface,
+                    category.member
+                );
+            }
+        }
+    }
+
+    // The D-Bus interface and member of every event signal in the introspection XML that
+    // atspi-common ships, as found through `cargo metadata`.
+    fn atspi_signals() -> Vec<(String, String)> {
+        let output = std::process::Command::new(env!("CARGO"))
+            .args(["metadata", "--format-version", "1", "--offline"])
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .output()
+            .unwrap();
+        let metadata: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let manifest = metadata["packages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|package| package["name"] == "atspi-common")
+            .and_then(|package| package["manifest_path"].as_str())
+            .unwrap();
+        let xml = std::path::Path::new(manifest).with_file_name("xml");
+
+        let mut signals = Vec::new();
+        for file in ["Event.xml", "Cache.xml", "Registry.xml", "Socket.xml"] {
+            let text = std::fs::read_to_string(xml.join(file)).unwrap();
+            let mut interface = "";
+            for element in text.split('<') {
+                if let Some(name) = element.strip_prefix("interface name=\"") {
+                    interface = name.split('"').next().unwrap();
+                } else if let Some(name) = element.strip_prefix("signal name=\"") {
+                    let member = name.split('"').next().unwrap();
+                    signals.push((interface.to_string(), member.to_string()));
+                }
+            }
+        }
+        signals
+    }
+
+    #[test]
+    fn categories_match_atspi_signals() {
+        let signals = atspi_signals();
+        for (interface, member) in &signals {
+            assert!(
+                INTERFACES
+                    .iter()
+                    .flat_map(|categories| categories.iter())
+                    .any(|cat| cat.interface == interface && cat.member == member),
+                "{interface}:{member} has no category"
+            );
+        }
+        for category in INTERFACES.iter().flat_map(|categories| categories.iter()) {
+            assert!(
+                signals
+                    .iter()
+                    .any(|(interface, member)| category.interface == interface
+                        && category.member == member),
+                "{}:{} is not an atspi signal",
+                category.interface,
+                category.member
+            );
+        }
+    }
+
+    #[test]
+    fn category_names_are_unique_per_interface() {
+        for categories in INTERFACES {
+            let mut names: Vec<String> = categories.iter().map(Category::name).collect();
+            names.sort();
+            names.dedup();
+            assert_eq!(names.len(), categories.len(), "{}", categories[0].interface);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use atspi::events::{
+    AvailableEvent, BusProperties, CacheEvents, DocumentEvents, EventListenerEvents, FocusEvents,
+    HasInterfaceName, KeyboardEvents, MouseEvents, ObjectEvents, TerminalEvents, WindowEvents,
+};
+use std::{error::Error, fmt::Write};
+
+use crate::aggregate::read_stats_files;
+use crate::categories::INTERFACES;
+use crate::counters::CounterStats;
+use crate::recommend::{Arm, interface_arms, member_arms, optimal_order};
+
+// For each interface, the variant of `Event` and the type that parses its signals.
+// Interfaces with a single event and no enum of their own are parsed by the event type.
+const DISPATCH: [(&str, &str, &str); 10] = [
+    (ObjectEvents::DBUS_INTERFACE, "Object", "ObjectEvents"),
+    (WindowEvents::DBUS_INTERFACE, "Window", "WindowEvents"),
+    (DocumentEvents::DBUS_INTERFACE, "Document", "DocumentEvents"),
+    (TerminalEvents::DBUS_INTERFACE, "Terminal", "TerminalEvents"),
+    (MouseEvents::DBUS_INTERFACE, "Mouse", "MouseEvents"),
+    (KeyboardEvents::DBUS_INTERFACE, "Keyboard", "KeyboardEvents"),
+    (
+        EventListenerEvents::DBUS_INTERFACE,
+        "Listener",
+        "EventListenerEvents",
+    ),
+    (CacheEvents::DBUS_INTERFACE, "Cache", "CacheEvents"),
+    (FocusEvents::DBUS_INTERFACE, "Focus", "FocusEvents"),
+    (
+        <AvailableEvent as BusProperties>::DBUS_INTERFACE,
+        "Available",
+        "AvailableEvent",
+    ),
+];
+
+// The generated code is meant for atspi-common, which names event types by their path
+// under `crate::events`.
+fn event_path(event_type: &str) -> String {
+    format!("crate::events::{event_type}")
+}
+
+fn count_comment(arm: &Arm, total: u64) -> String {
+    let percentage = if total > 0 {
+        arm.count as f64 / total as f64 * 100.0
+    } else {
+        0.0
+    };
+    format!("// {} events ({percentage:.2}%)", arm.count)
+}
+
+// `impl TryFrom<&zbus::Message> for Event`, with the interfaces in the order of `arms`.
+fn interface_dispatch(out: &mut String, arms: &[Arm]) -> std::fmt::Result {
+    let total = arms.iter().map(|arm| arm.count).sum();
+
+    writeln!(out, "#[cfg(feature = \"zbus\")]")?;
+    writeln!(out, "impl TryFrom<&zbus::Message> for Event {{")?;
+    writeln!(out, "\ttype Error = AtspiError;")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "\tfn try_from(msg: &zbus::Message) -> Result<Event, AtspiError> {{"
+    )?;
+    writeln!(out, "\t\tlet header = msg.header();")?;
+    writeln!(
+        out,
+        "\t\tlet interface = header.interface().ok_or(AtspiError::MissingInterface)?;"
+    )?;
+    writeln!(out, "\t\tlet interface_str = interface.as_str();")?;
+    writeln!(out)?;
+    writeln!(out, "\t\tmatch interface_str {{")?;
+
+    for arm in arms {
+        let Some((_, variant, parser)) = DISPATCH.iter().find(|(iface, _, _)| *iface == arm.name)
+        else {
+            continue;
+        };
+        writeln!(out, "\t\t\t{}", count_comment(arm, total))?;
+        writeln!(
+            out,
+            "\t\t\t<{parser} as HasInterfaceName>::DBUS_INTERFACE => {{"
+        )?;
+        if INTERFACES[arm.index].len() == 1 && *parser == INTERFACES[arm.index][0].event_type {
+            writeln!(out, "\t\t\t\tOk({parser}::try_from(msg)?.into())")?;
+        } else {
+            writeln!(
+                out,
+                "\t\t\t\tOk(Event::{variant}({parser}::try_from_message_interface_checked(msg)?))"
+            )?;
+        }
+        writeln!(out, "\t\t\t}}")?;
+    }
+
+    writeln!(out, "\t\t\t_ => Err(AtspiError::InterfaceMatch(format!(")?;
+    writeln!(
+        out,
+        "\t\t\t\t\"No events found with interface {{interface_str}}\""
+    )?;
+    writeln!(out, "\t\t\t))),")?;
+    writeln!(out, "\t\t}}")?;
+    writeln!(out, "\t}}")?;
+    writeln!(out, "}}")
+}
+
+// `impl EventWrapperMessageConversion` for the enum of the interface at `index` in
+// `INTERFACES`, with the members in the order of `arms`.
+fn member_dispatch(out: &mut String, parser: &str, index: usize, arms: &[Arm]) -> std::fmt::Result {
+    let total = arms.iter().map(|arm| arm.count).sum();
+
+    writeln!(out, "#[cfg(feature = \"zbus\")]")?;
+    writeln!(out, "impl EventWrapperMessageConversion for {parser} {{")?;
+    writeln!(
+        out,
+        "\tfn try_from_message_interface_checked(msg: &zbus::Message) -> Result<Self, AtspiError> {{"
+    )?;
+    writeln!(out, "\t\tlet header = msg.header();")?;
+    writeln!(
+        out,
+        "\t\tlet member = header.member().ok_or(AtspiError::MissingMember)?;"
+    )?;
+    writeln!(out, "\t\tmatch member.as_str() {{")?;
+
+    for arm in arms {
+        let categories: Vec<_> = INTERFACES[index]
+            .iter()
+            .filter(|cat| cat.member == arm.name)
+            .collect();
+        let first = event_path(categories[0].event_type);
+
+        writeln!(out, "\t\t\t{}", count_comment(arm, total))?;
+        writeln!(out, "\t\t\t{first}::DBUS_MEMBER => {{")?;
+
+        if let [category] = categories[..] {
+            writeln!(
+                out,
+                "\t\t\t\tOk({parser}::{}({first}::from_message_unchecked(msg)?))",
+                category.variant
+            )?;
+        } else {
+            // Events that share a member differ by their body signature.
+            writeln!(out, "\t\t\t\tlet body = msg.body();")?;
+            writeln!(out, "\t\t\t\tlet sig = body.signature();")?;
+            for (i, category) in categories.iter().enumerate() {
+                let path = event_path(category.event_type);
+                let branch = if i == 0 { "if" } else { "} else if" };
+                writeln!(
+                    out,
+                    "\t\t\t\t{branch} sig == <<{path} as MessageConversion>::Body as zvariant::Type>::SIGNATURE {{"
+                )?;
+                writeln!(
+                    out,
+                    "\t\t\t\t\tOk({parser}::{}({path}::from_message_unchecked(msg)?))",
+                    category.variant
+                )?;
+            }
+            writeln!(out, "\t\t\t\t}} else {{")?;
+            writeln!(out, "\t\t\t\t\tErr(AtspiError::SignatureMatch(format!(")?;
+            writeln!(
+                out,
+                "\t\t\t\t\t\t\"No matching event for signature {{:?}} in interface {{}}\","
+            )?;
+            writeln!(out, "\t\t\t\t\t\tsig,")?;
+            writeln!(out, "\t\t\t\t\t\tSelf::DBUS_INTERFACE")?;
+            writeln!(out, "\t\t\t\t\t)))")?;
+            writeln!(out, "\t\t\t\t}}")?;
+        }
+        writeln!(out, "\t\t\t}}")?;
+    }
+
+    writeln!(out, "\t\t\t_ => Err(AtspiError::MemberMatch(format!(")?;
+    writeln!(out, "\t\t\t\t\"No member {{}} in {{}}\",")?;
+    writeln!(out, "\t\t\t\tmember.as_str(),")?;
+    writeln!(out, "\t\t\t\tSelf::DBUS_INTERFACE")?;
+    writeln!(out, "\t\t\t))),")?;
+    writeln!(out, "\t\t}}")?;
+    writeln!(out, "\t}}")?;
+    writeln!(out, "}}")
+}
+
+/// Print Rust source for atspi's event parser, matching interfaces and then members in the
+/// order of their frequency in the stats files in the current directory.
+///
+/// The output follows the shape of the parser in atspi-common: `TryFrom<&zbus::Message>`
+/// for `Event`, then `EventWrapperMessageConversion` for each interface's event enum.
+pub fn codegen() -> Result<(), Box<dyn Error>> {
+    let aggregate = read_stats_files()?;
+    let counters = &aggregate.counters;
+    let interfaces = optimal_order(&interface_arms(counters));
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "// Generated by atspi-counters {} from {} events in {} stats files.",
+        env!("CARGO_PKG_VERSION"),
+        counters.total(),
+        aggregate.files
+    )?;
+    writeln!(
+        out,
+        "// Interfaces and members are matched in order of frequency, the most frequent first."
+    )?;
+    writeln!(out)?;
+    interface_dispatch(&mut out, &interfaces)?;
+
+    for arm in &interfaces {
+        let Some((_, _, parser)) = DISPATCH.iter().find(|(iface, _, _)| *iface == arm.name) else {
+            continue;
+        };
+        if INTERFACES[arm.index].len() == 1 && *parser == INTERFACES[arm.index][0].event_type {
+            continue;
+        }
+
+        writeln!(out)?;
+        member_dispatch(
+            &mut out,
+            parser,
+            arm.index,
+            &optimal_order(&member_arms(counters, arm.index)),
+        )?;
+    }
+
+    print!("{out}");
+    Ok(())
+}
@@ -1,310 +1,224 @@
-#![allow(unused)]
-use atspi::events::{
-    AvailableEvent, CacheEvents, DocumentEvents, Event, EventListenerEvents, FocusEvents,
-    KeyboardEvents, MouseEvents, ObjectEvents, TerminalEvents, WindowEvents,
-};
-use std::sync::atomic::AtomicU32;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::categories::{INTERFACES, interface_name};
+use crate::details::detail_counters;
 
 pub trait Getters {
-    fn get_categories(&self) -> &[(&'static str, AtomicU32)];
-    fn get_total(&self) -> &AtomicU32;
+    fn name(&self) -> &str;
+    fn get_children(&self) -> &[Counter];
+    fn get_total(&self) -> &AtomicU64;
 }
 
 pub trait CounterStats
 where
     Self: Getters,
 {
-    fn increment(&self, category: &'static str) {
-        self.get_total()
-            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-
-        for (cat, count) in self.get_categories() {
-            if *cat == category {
-                count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-            }
-        }
-    }
-
-    fn total(&self) -> u32 {
-        self.get_total().load(std::sync::atomic::Ordering::Relaxed)
+    /// Count one event at `path`, e.g. `["object", "state-changed"]`.
+    fn increment(&self, path: &[&str]) {
+        self.add(path, 1);
     }
 
-    fn pretty_print_stats(&self) {
-        println!("Total events: {}", self.total());
-        let mut stats: Vec<(&'static str, u32)> = self
-            .get_categories()
-            .iter()
-            .map(|(cat, count)| (*cat, count.load(std::sync::atomic::Ordering::Relaxed)))
-            .collect();
-        stats.sort_by(|a, b| b.1.cmp(&a.1));
+    /// Add `count` events at `path`.
+    /// Every counter along the path is incremented, so each counter's total
+    /// includes the events of its children.
+    fn add(&self, path: &[&str], count: u64) {
+        add_saturating(self.get_total(), count);
 
-        for (cat, count) in stats {
-            let percentage = if self.total() > 0 {
-                (count as f32 / self.total() as f32) * 100.0
-            } else {
-                0.0
-            };
-            // Position these in fixed width columns
-            print!("{:<30}", cat);
-            print!("{:<10}", count);
-            println!("{:.2}%", percentage);
+        if let Some((first, rest)) = path.split_first()
+            && let Some(child) = self.child(first)
+        {
+            child.add(rest, count);
         }
     }
-}
 
-pub struct InterfaceCount {
-    total: AtomicU32,
-    categories: [(&'static str, AtomicU32); 10],
-}
+    /// Count one event in the counter tree made by `new_counter_tree`, by the positions of
+    /// its interface in `INTERFACES` and of its category within that interface.
+    /// Returns the counter of the category, to count the details of the event beneath.
+    fn increment_index(&self, interface: usize, category: usize) -> &Counter {
+        let iface = &self.get_children()[interface];
+        let member = &iface.get_children()[category];
 
-impl InterfaceCount {
-    pub fn new() -> Self {
-        InterfaceCount {
-            total: AtomicU32::new(0),
-            categories: [
-                ("object", AtomicU32::new(0)),
-                ("window", AtomicU32::new(0)),
-                ("document", AtomicU32::new(0)),
-                ("terminal", AtomicU32::new(0)),
-                ("mouse", AtomicU32::new(0)),
-                ("keyboard", AtomicU32::new(0)),
-                ("listener", AtomicU32::new(0)),
-                ("cache", AtomicU32::new(0)),
-                ("focus", AtomicU32::new(0)),
-                ("available", AtomicU32::new(0)),
-            ],
-        }
+        add_saturating(self.get_total(), 1);
+        add_saturating(iface.get_total(), 1);
+        add_saturating(member.get_total(), 1);
+        member
     }
-}
 
-impl Getters for InterfaceCount {
-    fn get_categories(&self) -> &[(&'static str, AtomicU32)] {
-        &self.categories
-    }
-
-    fn get_total(&self) -> &AtomicU32 {
-        &self.total
+    /// Count one event in the detail counters beneath this counter, by their positions at
+    /// each level. This counter itself is left alone, it was counted with `increment_index`.
+    fn increment_detail(&self, path: &[usize]) {
+        if let Some((first, rest)) = path.split_first()
+            && let Some(child) = self.get_children().get(*first)
+        {
+            add_saturating(child.get_total(), 1);
+            child.increment_detail(rest);
+        }
     }
-}
-
-impl CounterStats for InterfaceCount {}
-
-pub struct ObjectCount {
-    total: AtomicU32,
-    categories: [(&'static str, AtomicU32); 22],
-}
 
-impl ObjectCount {
-    pub fn new() -> Self {
-        ObjectCount {
-            total: AtomicU32::new(0),
-            categories: [
-                ("property-change", AtomicU32::new(0)),
-                ("bounds-changed", AtomicU32::new(0)),
-                ("link-selected", AtomicU32::new(0)),
-                ("state-changed", AtomicU32::new(0)),
-                ("children-changed", AtomicU32::new(0)),
-                ("visible-data-changed", AtomicU32::new(0)),
-                ("selection-changed", AtomicU32::new(0)),
-                ("model-changed", AtomicU32::new(0)),
-                ("active-descendant-changed", AtomicU32::new(0)),
-                ("announcement", AtomicU32::new(0)),
-                ("attributes-changed", AtomicU32::new(0)),
-                ("row-inserted", AtomicU32::new(0)),
-                ("row-reordered", AtomicU32::new(0)),
-                ("row-deleted", AtomicU32::new(0)),
-                ("column-inserted", AtomicU32::new(0)),
-                ("column-reordered", AtomicU32::new(0)),
-                ("column-deleted", AtomicU32::new(0)),
-                ("text-bounds-changed", AtomicU32::new(0)),
-                ("text-selection-changed", AtomicU32::new(0)),
-                ("text-changed", AtomicU32::new(0)),
-                ("text-attributes-changed", AtomicU32::new(0)),
-                ("text-caret-moved", AtomicU32::new(0)),
-            ],
+    /// Add the counts of `other`, a counter tree of the same shape, to this one.
+    fn merge(&self, other: &(impl CounterStats + ?Sized)) {
+        add_saturating(self.get_total(), other.total());
+        for (child, other_child) in self.get_children().iter().zip(other.get_children()) {
+            child.merge(other_child);
         }
     }
-}
 
-impl Getters for ObjectCount {
-    fn get_categories(&self) -> &[(&'static str, AtomicU32)] {
-        &self.categories
+    fn child(&self, name: &str) -> Option<&Counter> {
+        self.get_children()
+            .iter()
+            .find(|child| child.name() == name)
     }
 
-    fn get_total(&self) -> &AtomicU32 {
-        &self.total
+    fn total(&self) -> u64 {
+        self.get_total().load(Ordering::Relaxed)
     }
-}
 
-impl CounterStats for ObjectCount {}
-
-pub struct WindowCount {
-    total: AtomicU32,
-    categories: [(&'static str, AtomicU32); 19],
-}
-
-impl WindowCount {
-    pub fn new() -> Self {
-        WindowCount {
-            total: AtomicU32::new(0),
-            categories: [
-                ("property-change", AtomicU32::new(0)),
-                ("minimize", AtomicU32::new(0)),
-                ("maximize", AtomicU32::new(0)),
-                ("restore", AtomicU32::new(0)),
-                ("close", AtomicU32::new(0)),
-                ("create", AtomicU32::new(0)),
-                ("reparent", AtomicU32::new(0)),
-                ("desktop-create", AtomicU32::new(0)),
-                ("desktop-destroy", AtomicU32::new(0)),
-                ("destroy", AtomicU32::new(0)),
-                ("activate", AtomicU32::new(0)),
-                ("deactivate", AtomicU32::new(0)),
-                ("raise", AtomicU32::new(0)),
-                ("lower", AtomicU32::new(0)),
-                ("move", AtomicU32::new(0)),
-                ("resize", AtomicU32::new(0)),
-                ("shade", AtomicU32::new(0)),
-                ("uushade", AtomicU32::new(0)),
-                ("restyle", AtomicU32::new(0)),
-            ],
-        }
+    /// Whether this counter has reached `u64::MAX`, so its total is only a lower bound.
+    fn saturated(&self) -> bool {
+        self.total() == u64::MAX
     }
-}
 
-impl Getters for WindowCount {
-    fn get_categories(&self) -> &[(&'static str, AtomicU32)] {
-        &self.categories
+    /// Whether this counter or any counter beneath it has saturated.
+    fn any_saturated(&self) -> bool {
+        self.saturated()
+            || self
+                .get_children()
+                .iter()
+                .any(|child| child.any_saturated())
     }
 
-    fn get_total(&self) -> &AtomicU32 {
-        &self.total
+    /// Print the children of this counter, with their own children nested underneath.
+    fn pretty_print_stats(&self) {
+        self.pretty_print_levels(usize::MAX);
     }
-}
-
-impl CounterStats for WindowCount {}
-
-pub struct TerminalCount {
-    total: AtomicU32,
-    categories: [(&'static str, AtomicU32); 5],
-}
 
-impl TerminalCount {
-    pub fn new() -> Self {
-        TerminalCount {
-            total: AtomicU32::new(0),
-            categories: [
-                ("line-changed", AtomicU32::new(0)),
-                ("column-count-changed", AtomicU32::new(0)),
-                ("line-count-changed", AtomicU32::new(0)),
-                ("application-changed", AtomicU32::new(0)),
-                ("char-width-changed", AtomicU32::new(0)),
-            ],
-        }
+    /// Print at most `levels` levels of children of this counter.
+    /// Nested counters without events are left out.
+    fn pretty_print_levels(&self, levels: usize) {
+        println!("Total events: {}{}", self.total(), saturation_marker(self));
+        print_rows(self.get_children(), self.total(), 0, levels, 1);
     }
-}
 
-impl Getters for TerminalCount {
-    fn get_categories(&self) -> &[(&'static str, AtomicU32)] {
-        &self.categories
+    /// Print the children of this counter like `pretty_print_stats`, for a counter of
+    /// something other than events, e.g. "bursts".
+    fn pretty_print_units(&self, units: &str) {
+        println!("Total {units}: {}{}", self.total(), saturation_marker(self));
+        print_rows(self.get_children(), self.total(), 0, usize::MAX, 1);
     }
 
-    fn get_total(&self) -> &AtomicU32 {
-        &self.total
+    /// Print the children of this counter like `pretty_print_stats`, leaving out counters
+    /// without events.
+    fn pretty_print_active(&self) {
+        println!("Total events: {}{}", self.total(), saturation_marker(self));
+        print_rows(self.get_children(), self.total(), 0, usize::MAX, 0);
     }
 }
 
-impl CounterStats for TerminalCount {}
-
-pub struct MouseCount {
-    total: AtomicU32,
-    categories: [(&'static str, AtomicU32); 3],
-}
-
-impl MouseCount {
-    pub fn new() -> Self {
-        MouseCount {
-            total: AtomicU32::new(0),
-            categories: [
-                ("abs", AtomicU32::new(0)),
-                ("rel", AtomicU32::new(0)),
-                ("button", AtomicU32::new(0)),
-            ],
-        }
+// Counters stick at `u64::MAX` rather than wrap around to zero.
+// Incrementing is a single `fetch_add`; only an add that overflows pays for the extra store.
+fn add_saturating(total: &AtomicU64, count: u64) {
+    let previous = total.fetch_add(count, Ordering::Relaxed);
+    if previous.checked_add(count).is_none() {
+        total.store(u64::MAX, Ordering::Relaxed);
     }
 }
 
-impl Getters for MouseCount {
-    fn get_categories(&self) -> &[(&'static str, AtomicU32)] {
-        &self.categories
-    }
-
-    fn get_total(&self) -> &AtomicU32 {
-        &self.total
+fn saturation_marker(counter: &(impl CounterStats + ?Sized)) -> &'static str {
+    if counter.saturated() {
+        " (saturated)"
+    } else {
+        ""
     }
 }
 
-impl CounterStats for MouseCount {}
+// Rows without events are left out from depth `active_from` on.
+fn print_rows(children: &[Counter], total: u64, depth: usize, levels: usize, active_from: usize) {
+    if depth >= levels {
+        return;
+    }
 
-pub struct DocumentCount {
-    total: AtomicU32,
-    categories: [(&'static str, AtomicU32); 6],
-}
+    let mut stats: Vec<&Counter> = children.iter().collect();
+    stats.sort_by_key(|child| std::cmp::Reverse(child.total()));
 
-impl DocumentCount {
-    pub fn new() -> Self {
-        DocumentCount {
-            total: AtomicU32::new(0),
-            categories: [
-                ("load-complete", AtomicU32::new(0)),
-                ("reload", AtomicU32::new(0)),
-                ("load-stopped", AtomicU32::new(0)),
-                ("content-changed", AtomicU32::new(0)),
-                ("attributes-changed", AtomicU32::new(0)),
-                ("page-changed", AtomicU32::new(0)),
-            ],
+    for child in stats {
+        let count = child.total();
+        if depth >= active_from && count == 0 {
+            // Sorted by count, so the rest are empty too.
+            break;
+        }
+        let percentage = if total > 0 {
+            (count as f64 / total as f64) * 100.0
+        } else {
+            0.0
+        };
+        // Position these in fixed width columns, nested rows indented under their parent
+        let name = format!("{:indent$}{}", "", child.name(), indent = depth * 2);
+        print!("{:<30}", name);
+        print!("{:<9} ", count);
+        println!("{:.2}%{}", percentage, saturation_marker(child));
+
+        print_rows(child.get_children(), count, depth + 1, levels, active_from);
+    }
+}
+
+/// A counter with an optional level of child counters beneath it,
+/// e.g. interface → member → detail.
+pub struct Counter {
+    name: String,
+    total: AtomicU64,
+    children: Vec<Counter>,
+}
+
+impl Counter {
+    pub fn new(name: impl Into<String>) -> Self {
+        Counter {
+            name: name.into(),
+            total: AtomicU64::new(0),
+            children: Vec::new(),
         }
-    }
-}
-
-impl Getters for DocumentCount {
-    fn get_categories(&self) -> &[(&'static str, AtomicU32)] {
-        &self.categories
     }
 
-    fn get_total(&self) -> &AtomicU32 {
-        &self.total
+    pub fn with_children(name: impl Into<String>, children: Vec<Counter>) -> Self {
+        Counter {
+            name: name.into(),
+            total: AtomicU64::new(0),
+            children,
+        }
     }
 }
 
-impl CounterStats for DocumentCount {}
-
-pub struct CacheCount {
-    total: AtomicU32,
-    categories: [(&'static str, AtomicU32); 3],
-}
-
-impl CacheCount {
-    pub fn new() -> Self {
-        CacheCount {
-            total: AtomicU32::new(0),
-            categories: [
-                ("add", AtomicU32::new(0)),
-                ("legacy-add", AtomicU32::new(0)),
-                ("remove", AtomicU32::new(0)),
-            ],
-        }
+impl Getters for Counter {
+    fn name(&self) -> &str {
+        &self.name
     }
-}
 
-impl Getters for CacheCount {
-    fn get_categories(&self) -> &[(&'static str, AtomicU32)] {
-        &self.categories
+    fn get_children(&self) -> &[Counter] {
+        &self.children
     }
 
-    fn get_total(&self) -> &AtomicU32 {
+    fn get_total(&self) -> &AtomicU64 {
         &self.total
     }
 }
 
-impl CounterStats for CacheCount {}
+impl CounterStats for Counter {}
+
+/// The counter tree for all events: interface → member → detail, with one counter per category
+/// that atspi can parse and the detail counters of those that have them.
+pub fn new_counter_tree() -> Counter {
+    Counter::with_children(
+        "interface",
+        INTERFACES
+            .iter()
+            .map(|categories| {
+                Counter::with_children(
+                    interface_name(categories[0].interface),
+                    categories
+                        .iter()
+                        .map(|cat| Counter::with_children(cat.name(), detail_counters(cat)))
+                        .collect(),
+                )
+            })
+            .collect(),
+    )
+}
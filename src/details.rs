@@ -0,0 +1,535 @@
+use atspi::{
+    ObjectRef, Operation, StateSet,
+    events::{
+        BusProperties,
+        object::{
+            ChildrenChangedEvent, Property, PropertyChangeEvent, StateChangedEvent,
+            TextChangedEvent,
+        },
+    },
+};
+use zbus::zvariant::Signature;
+
+use crate::categories::Category;
+use crate::counters::{Counter, CounterStats};
+
+// Detail counters sit beneath the counter of a category and split its events further by what
+// they carry, e.g. state-changed by state. Like categories, they are counted by position.
+
+/// The detail counters beneath the counter of `category`, if it has any.
+pub fn detail_counters(category: &Category) -> Vec<Counter> {
+    if is::<StateChangedEvent>(category) {
+        state_counters()
+    } else if is::<PropertyChangeEvent>(category) {
+        property_counters()
+    } else if is::<TextChangedEvent>(category) {
+        text_counters()
+    } else if is::<ChildrenChangedEvent>(category) {
+        children_counters()
+    } else {
+        Vec::new()
+    }
+}
+
+fn is<E: BusProperties>(category: &Category) -> bool {
+    category.interface == E::DBUS_INTERFACE && category.member == E::DBUS_MEMBER
+}
+
+// state → enabled or disabled, with one counter per state in the order of its bit.
+fn state_counters() -> Vec<Counter> {
+    (0..u64::BITS)
+        .map_while(|bit| StateSet::from_bits(1 << bit).ok())
+        .flat_map(StateSet::iter)
+        .map(|state| {
+            Counter::with_children(
+                state.to_static_str(),
+                vec![Counter::new("enabled"), Counter::new("disabled")],
+            )
+        })
+        .collect()
+}
+
+/// Count a state-changed event beneath `counter`, the counter of its category.
+pub fn count_state_changed(ev: &StateChangedEvent, counter: &Counter) {
+    let state = StateSet::new(ev.state).bits().trailing_zeros() as usize;
+    let enabled = if ev.enabled { 0 } else { 1 };
+    counter.increment_detail(&[state, enabled]);
+}
+
+// The properties atspi knows, by their names on the bus, and "other" for the rest,
+// in the order of their counters.
+const PROPERTIES: [&str; 11] = [
+    "accessible-name",
+    "accessible-description",
+    "accessible-role",
+    "accessible-parent",
+    "accessible-table-caption",
+    "table-column-description",
+    "table-column-header",
+    "table-row-description",
+    "table-row-header",
+    "table-summary",
+    "other",
+];
+
+// The D-Bus types of property values, coarsely, in the order of their counters.
+#[derive(Debug, Clone, Copy)]
+enum ValueType {
+    String,
+    ObjectPath,
+    Boolean,
+    Int32,
+    Uint32,
+    Int64,
+    Uint64,
+    Double,
+    Struct,
+    Array,
+    Variant,
+    Other,
+}
+
+const VALUE_TYPES: [&str; 12] = [
+    "string",
+    "object-path",
+    "boolean",
+    "int32",
+    "uint32",
+    "int64",
+    "uint64",
+    "double",
+    "struct",
+    "array",
+    "variant",
+    "other",
+];
+
+impl ValueType {
+    fn of(signature: &Signature) -> Self {
+        match signature {
+            Signature::Str => ValueType::String,
+            Signature::ObjectPath => ValueType::ObjectPath,
+            Signature::Bool => ValueType::Boolean,
+            Signature::I32 => ValueType::Int32,
+            Signature::U32 => ValueType::Uint32,
+            Signature::I64 => ValueType::Int64,
+            Signature::U64 => ValueType::Uint64,
+            Signature::F64 => ValueType::Double,
+            Signature::Structure(_) => ValueType::Struct,
+            Signature::Array(_) | Signature::Dict { .. } => ValueType::Array,
+            Signature::Variant => ValueType::Variant,
+            _ => ValueType::Other,
+        }
+    }
+}
+
+// property → type of its value.
+fn property_counters() -> Vec<Counter> {
+    PROPERTIES
+        .iter()
+        .map(|property| {
+            Counter::with_children(
+                *property,
+                VALUE_TYPES.iter().map(|name| Counter::new(*name)).collect(),
+            )
+        })
+        .collect()
+}
+
+/// Count a property-change event beneath `counter`, the counter of its category.
+///
+/// The values of the properties atspi knows have the type atspi parsed them from;
+/// of other properties, the type is read from the value.
+pub fn count_property_change(ev: &PropertyChangeEvent, counter: &Counter) {
+    let (property, value_type) = match &ev.value {
+        Property::Name(_) => (0, ValueType::String),
+        Property::Description(_) => (1, ValueType::String),
+        Property::Role(_) => (2, ValueType::Uint32),
+        Property::Parent(_) => (3, ValueType::Struct),
+        Property::TableCaption(_) => (4, ValueType::String),
+        Property::TableColumnDescription(_) => (5, ValueType::String),
+        Property::TableColumnHeader(_) => (6, ValueType::String),
+        Property::TableRowDescription(_) => (7, ValueType::String),
+        Property::TableRowHeader(_) => (8, ValueType::String),
+        Property::TableSummary(_) => (9, ValueType::String),
+        Property::Other((_, value)) => (10, ValueType::of(value.value_signature())),
+        _ => (10, ValueType::Other),
+    };
+    counter.increment_detail(&[property, value_type as usize]);
+}
+
+// Sizes, like the length of changed text in characters, in the order of their counters.
+// A length of 0 or less is what a toolkit may send, but changes nothing.
+const SIZES: [&str; 5] = ["0", "1", "2-10", "11-100", ">100"];
+
+fn size_index(size: i64) -> usize {
+    match size {
+        ..=0 => 0,
+        1 => 1,
+        2..=10 => 2,
+        11..=100 => 3,
+        _ => 4,
+    }
+}
+
+// insert or delete → length of the changed text.
+fn text_counters() -> Vec<Counter> {
+    ["insert", "delete"]
+        .into_iter()
+        .map(|operation| {
+            Counter::with_children(
+                operation,
+                SIZES.iter().map(|name| Counter::new(*name)).collect(),
+            )
+        })
+        .collect()
+}
+
+/// Count a text-changed event beneath `counter`, the counter of its category,
+/// by the length it reports. The text itself is not kept.
+pub fn count_text_changed(ev: &TextChangedEvent, counter: &Counter) {
+    let operation = match ev.operation {
+        Operation::Insert => 0,
+        Operation::Delete => 1,
+    };
+    counter.increment_detail(&[operation, size_index(ev.length.into())]);
+}
+
+// The positions of added or removed children, in the order of their counters.
+// Toolkits send a negative index when they don't know it.
+const CHILD_INDICES: [&str; 5] = ["<0", "0", "1-10", "11-100", ">100"];
+
+// add or remove → index of the child.
+fn children_counters() -> Vec<Counter> {
+    ["add", "remove"]
+        .into_iter()
+        .map(|operation| {
+            Counter::with_children(
+                operation,
+                CHILD_INDICES
+                    .iter()
+                    .map(|name| Counter::new(*name))
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+/// Count a children-changed event beneath `counter`, the counter of its category.
+pub fn count_children_changed(ev: &ChildrenChangedEvent, counter: &Counter) {
+    let operation = match ev.operation {
+        Operation::Insert => 0,
+        Operation::Delete => 1,
+    };
+    let index = match ev.index_in_parent {
+        ..0 => 0,
+        0 => 1,
+        1..=10 => 2,
+        11..=100 => 3,
+        _ => 4,
+    };
+    counter.increment_detail(&[operation, index]);
+}
+
+/// The counter of runs of children-changed events by their length, as `ChildrenBursts`
+/// counts them.
+pub fn bursts_counter() -> Counter {
+    Counter::with_children(
+        "bursts",
+        SIZES[1..].iter().map(|name| Counter::new(*name)).collect(),
+    )
+}
+
+/// Runs of children-changed events for one parent object, by their length: how much of the
+/// tree a toolkit rebuilds at once.
+///
+/// A run ends at the first children-changed event for another parent; events of other
+/// categories in between don't end it. Runs are told apart by order alone, so they come out
+/// the same when a capture is replayed.
+pub struct ChildrenBursts {
+    counter: Counter,
+    current: Option<(ObjectRef, u64)>,
+}
+
+impl ChildrenBursts {
+    pub fn new() -> Self {
+        ChildrenBursts {
+            counter: bursts_counter(),
+            current: None,
+        }
+    }
+
+    pub fn record(&mut self, ev: &ChildrenChangedEvent) {
+        match &mut self.current {
+            Some((parent, length)) if *parent == ev.item => *length += 1,
+            _ => {
+                if let Some((_, length)) = self.current.replace((ev.item.clone(), 1)) {
+                    count_burst(&self.counter, length);
+                }
+            }
+        }
+    }
+
+    /// The runs counted so far, including the one that hasn't ended yet.
+    pub fn counter(&self) -> Counter {
+        let counter = bursts_counter();
+        counter.merge(&self.counter);
+        if let Some((_, length)) = &self.current {
+            count_burst(&counter, *length);
+        }
+        counter
+    }
+}
+
+// Bursts end far less often than events are counted, so they are counted by name.
+fn count_burst(counter: &Counter, length: u64) {
+    let length = i64::try_from(length).unwrap_or(i64::MAX);
+    counter.increment(&[SIZES[size_index(length)]]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::counters::Getters;
+    use atspi::{Role, State};
+    use zbus::zvariant::Value;
+
+    // The count beneath `counter` at the path of detail names.
+    fn count(counter: &Counter, path: &[&str]) -> u64 {
+        path.iter()
+            .try_fold(counter, |counter, name| counter.child(name))
+            .map_or(0, |counter| counter.total())
+    }
+
+    #[test]
+    fn states_are_counted_by_their_bit() {
+        let counter = Counter::with_children("state-changed", state_counters());
+        let states = [
+            State::Invalid,
+            State::Active,
+            State::Focused,
+            State::Visible,
+            State::ReadOnly,
+        ];
+        for state in states {
+            // The counter of each state sits at the position of its bit.
+            let bit = StateSet::new(state).bits().trailing_zeros() as usize;
+            assert_eq!(counter.get_children()[bit].name(), state.to_static_str());
+
+            for enabled in [true, true, false] {
+                let ev = StateChangedEvent {
+                    state,
+                    enabled,
+                    ..Default::default()
+                };
+                count_state_changed(&ev, &counter);
+            }
+        }
+
+        for state in states {
+            let name = state.to_static_str();
+            assert_eq!(count(&counter, &[name]), 3, "{name}");
+            assert_eq!(count(&counter, &[name, "enabled"]), 2, "{name}");
+            assert_eq!(count(&counter, &[name, "disabled"]), 1, "{name}");
+        }
+        assert_eq!(count(&counter, &["checked"]), 0);
+    }
+
+    #[test]
+    fn value_types_sit_at_their_names() {
+        let types = [
+            ValueType::String,
+            ValueType::ObjectPath,
+            ValueType::Boolean,
+            ValueType::Int32,
+            ValueType::Uint32,
+            ValueType::Int64,
+            ValueType::Uint64,
+            ValueType::Double,
+            ValueType::Struct,
+            ValueType::Array,
+            ValueType::Variant,
+            ValueType::Other,
+        ];
+        let names: Vec<&str> = types
+            .iter()
+            .map(|value_type| VALUE_TYPES[*value_type as usize])
+            .collect();
+        assert_eq!(names, VALUE_TYPES);
+    }
+
+    #[test]
+    fn properties_are_counted_by_name_and_value_type() {
+        let counter = Counter::with_children("property-change", property_counters());
+        let other = |value: Value<'static>| {
+            Property::Other(("mine".to_string(), value.try_into().unwrap()))
+        };
+        let changes = [
+            (
+                Property::Name("OK".to_string()),
+                "accessible-name",
+                "string",
+            ),
+            (
+                Property::Description(String::new()),
+                "accessible-description",
+                "string",
+            ),
+            (Property::Role(Role::Button), "accessible-role", "uint32"),
+            (
+                Property::Parent(ObjectRef::default()),
+                "accessible-parent",
+                "struct",
+            ),
+            (
+                Property::TableCaption(String::new()),
+                "accessible-table-caption",
+                "string",
+            ),
+            (
+                Property::TableColumnDescription(String::new()),
+                "table-column-description",
+                "string",
+            ),
+            (
+                Property::TableColumnHeader(String::new()),
+                "table-column-header",
+                "string",
+            ),
+            (
+                Property::TableRowDescription(String::new()),
+                "table-row-description",
+                "string",
+            ),
+            (
+                Property::TableRowHeader(String::new()),
+                "table-row-header",
+                "string",
+            ),
+            (
+                Property::TableSummary(String::new()),
+                "table-summary",
+                "string",
+            ),
+            (other(Value::from(true)), "other", "boolean"),
+            (other(Value::from(-1i32)), "other", "int32"),
+            (other(Value::from(1.5f64)), "other", "double"),
+            (other(Value::from("text")), "other", "string"),
+            (other(Value::from(vec![1u32, 2])), "other", "array"),
+        ];
+        let expected: Vec<(&str, &str)> = changes
+            .iter()
+            .map(|(_, property, value_type)| (*property, *value_type))
+            .collect();
+        for (value, _, _) in changes {
+            let ev = PropertyChangeEvent {
+                value,
+                ..Default::default()
+            };
+            count_property_change(&ev, &counter);
+        }
+
+        for (property, value_type) in expected {
+            assert_eq!(
+                count(&counter, &[property, value_type]),
+                1,
+                "{property} {value_type}"
+            );
+        }
+        assert_eq!(count(&counter, &["other"]), 5);
+        assert_eq!(count(&counter, &["accessible-role", "string"]), 0);
+    }
+
+    #[test]
+    fn sizes_break_at_their_bounds() {
+        let sizes: Vec<&str> = [-1, 0, 1, 2, 10, 11, 100, 101, i64::MAX]
+            .into_iter()
+            .map(|size| SIZES[size_index(size)])
+            .collect();
+        assert_eq!(
+            sizes,
+            [
+                "0", "0", "1", "2-10", "2-10", "11-100", "11-100", ">100", ">100"
+            ]
+        );
+    }
+
+    #[test]
+    fn text_changes_are_counted_by_operation_and_length() {
+        let counter = Counter::with_children("text-changed", text_counters());
+        for (operation, length) in [
+            (Operation::Insert, 1),
+            (Operation::Insert, 10),
+            (Operation::Delete, 11),
+            (Operation::Delete, 101),
+        ] {
+            let ev = TextChangedEvent {
+                operation,
+                length,
+                ..Default::default()
+            };
+            count_text_changed(&ev, &counter);
+        }
+
+        assert_eq!(count(&counter, &["insert"]), 2);
+        assert_eq!(count(&counter, &["insert", "1"]), 1);
+        assert_eq!(count(&counter, &["insert", "2-10"]), 1);
+        assert_eq!(count(&counter, &["delete", "11-100"]), 1);
+        assert_eq!(count(&counter, &["delete", ">100"]), 1);
+        assert_eq!(count(&counter, &["delete", "0"]), 0);
+    }
+
+    #[test]
+    fn children_changes_are_counted_by_operation_and_index() {
+        let counter = Counter::with_children("children-changed", children_counters());
+        for index in [-1, 0, 1, 10, 11, 100, 101] {
+            let ev = ChildrenChangedEvent {
+                operation: Operation::Insert,
+                index_in_parent: index,
+                ..Default::default()
+            };
+            count_children_changed(&ev, &counter);
+        }
+        let ev = ChildrenChangedEvent {
+            operation: Operation::Delete,
+            index_in_parent: i32::MIN,
+            ..Default::default()
+        };
+        count_children_changed(&ev, &counter);
+
+        let added: Vec<u64> = CHILD_INDICES
+            .iter()
+            .map(|index| count(&counter, &["add", index]))
+            .collect();
+        assert_eq!(added, [1, 1, 2, 2, 1]);
+        assert_eq!(count(&counter, &["remove"]), 1);
+        assert_eq!(count(&counter, &["remove", "<0"]), 1);
+    }
+
+    #[test]
+    fn bursts_end_at_another_parent() {
+        let of = |path: &str| ChildrenChangedEvent {
+            item: ObjectRef {
+                path: path.try_into().unwrap(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut bursts = ChildrenBursts::new();
+        for path in ["/a", "/a", "/a", "/b", "/a", "/a"] {
+            bursts.record(&of(path));
+        }
+
+        // The runs of /a and /b have ended, the second run of /a is still open.
+        let counter = bursts.counter();
+        assert_eq!(counter.total(), 3);
+        assert_eq!(count(&counter, &["1"]), 1);
+        assert_eq!(count(&counter, &["2-10"]), 2);
+
+        // Taking the counter doesn't end the open run, so another event for /a extends it.
+        bursts.record(&of("/a"));
+        let counter = bursts.counter();
+        assert_eq!(counter.total(), 3);
+        assert_eq!(count(&counter, &["2-10"]), 2);
+    }
+}
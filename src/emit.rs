@@ -0,0 +1,80 @@
+use std::{error::Error, path::Path, time::Duration};
+use tokio::time::{Instant, sleep_until};
+
+use crate::capture::{CaptureReader, Record};
+
+/// Pauses longer than this separate bursts of signals; `--burst` leaves them out.
+const BURST_GAP: Duration = Duration::from_millis(500);
+
+/// How to pace the signals of a capture.
+#[derive(Debug, Clone, Copy)]
+pub enum Pace {
+    /// Keep the time between signals, divided by the speed.
+    Timed { speed: f64 },
+    /// Keep the time between signals within a burst, divided by the speed, but leave out the
+    /// pauses between bursts.
+    Bursts { speed: f64 },
+    /// Send the signals as fast as the bus takes them.
+    Fast,
+}
+
+impl Pace {
+    // The time to wait before a signal that came `gap` after the previous one.
+    fn delay(self, gap: Duration) -> Duration {
+        match self {
+            Pace::Timed { speed } => gap.div_f64(speed),
+            Pace::Bursts { speed } if gap <= BURST_GAP => gap.div_f64(speed),
+            Pace::Bursts { .. } | Pace::Fast => Duration::ZERO,
+        }
+    }
+}
+
+/// Send the signals in the capture file at `path` to the bus at `address`, paced by `pace`.
+pub async fn emit(path: &Path, address: &str, pace: Pace) -> Result<(), Box<dyn Error>> {
+    let capture = CaptureReader::open(path)?;
+    let conn = zbus::connection::Builder::address(address)?.build().await?;
+    println!(
+        "Emitting capture of {} onto {address}",
+        capture.start.format("%Y-%m-%d %H:%M:%S")
+    );
+
+    let start = Instant::now();
+    let mut due = start;
+    let mut previous: Option<u64> = None;
+    let mut signals = 0u64;
+
+    for record in capture {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                eprintln!("{}: stopped at a damaged record: {e}", path.display());
+                break;
+            }
+        };
+
+        if let Some(previous) = previous {
+            let gap = Duration::from_micros(record.offset.saturating_sub(previous));
+            due += pace.delay(gap);
+            sleep_until(due).await;
+        }
+        previous = Some(record.offset);
+
+        // The bus fills in our own name as the sender.
+        let msg = Record {
+            sender: String::new(),
+            ..record
+        }
+        .to_message()?;
+        conn.send(&msg).await?;
+        signals += 1;
+    }
+
+    let elapsed = start.elapsed().as_secs_f64();
+    let rate = if elapsed > 0.0 {
+        signals as f64 / elapsed
+    } else {
+        0.0
+    };
+    println!("Signals emitted: {signals} in {elapsed:.3} s ({rate:.0} per second)");
+    Ok(())
+}
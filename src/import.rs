@@ -0,0 +1,517 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+use zbus::{
+    Message,
+    message::Type,
+    zvariant::{
+        Endian, Value,
+        serialized::{Context, Data},
+    },
+};
+
+use crate::session::Session;
+
+/// The interfaces of AT-SPI events all start with this.
+const ATSPI_INTERFACE_PREFIX: &str = "org.a11y.atspi.";
+
+// The pcap magic numbers for microsecond and nanosecond timestamps, read little-endian.
+// A file written on a big-endian machine has them byte-swapped.
+const PCAP_MAGIC: [u32; 2] = [0xa1b2_c3d4, 0xa1b2_3c4d];
+/// The link type of whole D-Bus messages, as written by `busctl capture` and
+/// `dbus-monitor --pcap`.
+const LINKTYPE_DBUS: u32 = 231;
+
+// A pcap file starts with a 24 byte header, the link type last:
+//
+// magic: u32, version: u16 u16, time zone: i32, accuracy: u32, snapshot length: u32, link type: u32
+//
+// Each packet has a 16 byte header before its data:
+//
+// seconds: u32, fraction: u32, captured length: u32, original length: u32
+const PCAP_HEADER_LEN: usize = 24;
+const PACKET_HEADER_LEN: usize = 16;
+
+// A pcapng file is a list of blocks, each starting with its type and length:
+//
+// type: u32, length: u32, body, length: u32
+//
+// A section header block starts each section, with a byte order magic that sets the byte order
+// of the blocks after it. An interface description block has its link type first; an enhanced
+// packet block refers to an interface by position in its section, a simple packet block is
+// always of the first interface.
+const SECTION_HEADER_BLOCK: u32 = 0x0a0d_0d0a;
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 1;
+const SIMPLE_PACKET_BLOCK: u32 = 3;
+const ENHANCED_PACKET_BLOCK: u32 = 6;
+
+// A D-Bus message header: endianness, message type, flags, protocol version, body length,
+// serial, then the header fields by their code.
+type Header<'a> = (u8, u8, u8, u8, u32, u32, Vec<(u8, Value<'a>)>);
+const PROTOCOL_VERSION: u8 = 1;
+/// The code of the header field with the number of file descriptors sent with a message.
+const UNIX_FDS_FIELD: u8 = 9;
+
+/// What was taken from one imported file.
+struct Imported {
+    format: &'static str,
+    signals: u64,
+    skipped: u64,
+}
+
+impl Imported {
+    fn new(format: &'static str) -> Self {
+        Imported {
+            format,
+            signals: 0,
+            skipped: 0,
+        }
+    }
+}
+
+/// Count the AT-SPI signals in `busctl capture` or `dbus-monitor --pcap` files and in
+/// `dbus-monitor` text dumps, and print the stats of all files together.
+pub fn import(files: &[PathBuf], raw_mode: bool) -> Result<(), Box<dyn Error>> {
+    let mut session = Session::new(raw_mode);
+    let mut signals = 0u64;
+
+    for path in files {
+        let imported =
+            import_file(&mut session, path).map_err(|e| format!("{}: {e}", path.display()))?;
+        println!(
+            "{}: {} signals ({})",
+            path.display(),
+            imported.signals,
+            imported.format
+        );
+        if imported.skipped > 0 {
+            eprintln!(
+                "{}: skipped {} truncated or malformed messages",
+                path.display(),
+                imported.skipped
+            );
+        }
+        signals += imported.signals;
+    }
+
+    println!("Signals imported: {signals}");
+    session.print();
+    Ok(())
+}
+
+// A pcap or pcapng file is told apart by its magic number; anything else has to be
+// `dbus-monitor` text.
+fn import_file(session: &mut Session, path: &Path) -> Result<Imported, Box<dyn Error>> {
+    let bytes = std::fs::read(path)?;
+
+    match bytes.first_chunk().map(|magic| u32::from_le_bytes(*magic)) {
+        Some(magic) if PCAP_MAGIC.contains(&magic) => import_pcap(session, &bytes, Endian::Little),
+        Some(magic) if PCAP_MAGIC.contains(&magic.swap_bytes()) => {
+            import_pcap(session, &bytes, Endian::Big)
+        }
+        Some(SECTION_HEADER_BLOCK) => import_pcapng(session, &bytes),
+        _ => {
+            let text = std::str::from_utf8(&bytes)
+                .map_err(|_| "neither a pcap or pcapng file nor `dbus-monitor` text")?;
+            Ok(import_monitor_text(session, text))
+        }
+    }
+}
+
+fn import_pcap(
+    session: &mut Session,
+    bytes: &[u8],
+    endian: Endian,
+) -> Result<Imported, Box<dyn Error>> {
+    let link_type = read_u32(bytes, PCAP_HEADER_LEN - 4, endian).ok_or("truncated pcap header")?;
+    if link_type != LINKTYPE_DBUS {
+        return Err(format!("pcap link type {link_type} is not D-Bus ({LINKTYPE_DBUS})").into());
+    }
+
+    let mut imported = Imported::new("pcap");
+    let mut at = PCAP_HEADER_LEN;
+    while at < bytes.len() {
+        let captured = read_u32(bytes, at + 8, endian);
+        let original = read_u32(bytes, at + 12, endian);
+        let start = at + PACKET_HEADER_LEN;
+        // A capture that was killed may end mid-packet; count what is there.
+        let (Some(captured), Some(original)) = (captured, original) else {
+            imported.skipped += 1;
+            break;
+        };
+        let Some(packet) = bytes.get(start..start + captured as usize) else {
+            imported.skipped += 1;
+            break;
+        };
+        at = start + packet.len();
+        count_packet(session, &mut imported, packet, original);
+    }
+
+    Ok(imported)
+}
+
+fn import_pcapng(session: &mut Session, bytes: &[u8]) -> Result<Imported, Box<dyn Error>> {
+    let mut imported = Imported::new("pcapng");
+    let mut endian = Endian::Little;
+    let mut link_types = Vec::new();
+
+    let mut at = 0;
+    while at < bytes.len() {
+        // The section header block type reads the same in either byte order.
+        if read_u32(bytes, at, endian) == Some(SECTION_HEADER_BLOCK) {
+            endian = match read_u32(bytes, at + 8, Endian::Little) {
+                Some(BYTE_ORDER_MAGIC) => Endian::Little,
+                Some(magic) if magic.swap_bytes() == BYTE_ORDER_MAGIC => Endian::Big,
+                _ => return Err("invalid pcapng section header".into()),
+            };
+            link_types.clear();
+        }
+
+        let block_type = read_u32(bytes, at, endian);
+        let len = read_u32(bytes, at + 4, endian).map(|len| len as usize);
+        // A capture that was killed may end mid-block; count what is there.
+        let (Some(block_type), Some(block)) =
+            (block_type, len.and_then(|len| bytes.get(at..at + len)))
+        else {
+            imported.skipped += 1;
+            break;
+        };
+        if block.len() < 12 {
+            return Err(format!("invalid pcapng block length {}", block.len()).into());
+        }
+        at += block.len();
+
+        match block_type {
+            INTERFACE_DESCRIPTION_BLOCK => {
+                let link_type = read_u32(block, 8, endian).map(|value| match endian {
+                    Endian::Little => value & 0xffff,
+                    Endian::Big => value >> 16,
+                });
+                link_types.push(link_type);
+            }
+            ENHANCED_PACKET_BLOCK => {
+                let interface = read_u32(block, 8, endian);
+                let captured = read_u32(block, 20, endian);
+                let original = read_u32(block, 24, endian);
+                if let (Some(interface), Some(captured), Some(original)) =
+                    (interface, captured, original)
+                    && link_types.get(interface as usize) == Some(&Some(LINKTYPE_DBUS))
+                {
+                    let packet = block.get(28..28 + captured as usize).unwrap_or_default();
+                    count_packet(session, &mut imported, packet, original);
+                }
+            }
+            SIMPLE_PACKET_BLOCK => {
+                // The packet is cut to the snapshot length, and padded to 4 bytes.
+                if let Some(original) = read_u32(block, 8, endian)
+                    && link_types.first() == Some(&Some(LINKTYPE_DBUS))
+                {
+                    let data = block.get(12..block.len() - 4).unwrap_or_default();
+                    let packet = &data[..data.len().min(original as usize)];
+                    count_packet(session, &mut imported, packet, original);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(imported)
+}
+
+// Count a packet if it holds an AT-SPI signal; `original` is its length before it was cut to
+// the snapshot length.
+fn count_packet(session: &mut Session, imported: &mut Imported, packet: &[u8], original: u32) {
+    if packet.len() < original as usize {
+        imported.skipped += 1;
+        return;
+    }
+    match message_from_bytes(packet) {
+        Some(Ok(msg)) if is_atspi_signal(&msg) => {
+            session.count(Ok(msg));
+            imported.signals += 1;
+        }
+        Some(Ok(_)) => {}
+        Some(Err(_)) | None => imported.skipped += 1,
+    }
+}
+
+// Signal lines of `dbus-monitor` look like this, followed by indented lines for the body:
+//
+// signal time=1700000000.123456 sender=:1.42 -> destination=(null destination) serial=7
+//     path=/org/a11y/atspi/accessible/12; interface=org.a11y.atspi.Event.Object; member=StateChanged
+//
+// (all on one line). Older versions leave out the time and write `dest=`.
+fn import_monitor_text(session: &mut Session, text: &str) -> Imported {
+    let mut imported = Imported::new("dbus-monitor");
+
+    for line in text.lines() {
+        let Some(fields) = line.strip_prefix("signal ") else {
+            continue;
+        };
+        let field = |name: &str| {
+            fields
+                .split_whitespace()
+                .find_map(|field| field.strip_prefix(name))
+                .map(|value| value.trim_end_matches(';'))
+        };
+
+        match (field("interface="), field("member=")) {
+            (Some(interface), Some(member)) if interface.starts_with(ATSPI_INTERFACE_PREFIX) => {
+                session.count_header(field("sender="), interface, member);
+                imported.signals += 1;
+            }
+            (Some(_), Some(_)) => {}
+            _ => imported.skipped += 1,
+        }
+    }
+
+    imported
+}
+
+fn is_atspi_signal(msg: &Message) -> bool {
+    msg.message_type() == Type::Signal
+        && msg
+            .header()
+            .interface()
+            .is_some_and(|name| name.starts_with(ATSPI_INTERFACE_PREFIX))
+}
+
+// A whole D-Bus message, or `None` if its header is not one of a D-Bus 1 message whose
+// header fields and body fill `bytes` exactly, or if it refers to file descriptors.
+fn message_from_bytes(bytes: &[u8]) -> Option<zbus::Result<Message>> {
+    let endian = match bytes.first()? {
+        b'l' => Endian::Little,
+        b'B' => Endian::Big,
+        _ => return None,
+    };
+    if *bytes.get(3)? != PROTOCOL_VERSION {
+        return None;
+    }
+    // The body length, then the length of the header fields, which are padded to 8 bytes.
+    let body_len = read_u32(bytes, 4, endian)? as usize;
+    let fields_len = read_u32(bytes, 12, endian)? as usize;
+    let header_len = 16usize
+        .checked_add(fields_len)?
+        .checked_next_multiple_of(8)?;
+    if header_len.checked_add(body_len)? != bytes.len() {
+        return None;
+    }
+
+    // The header, read on its own, must hold its fields, none of which may be UNIX_FDS:
+    // the descriptors of a captured message are gone.
+    let header = Data::new(&bytes[..header_len], Context::new_dbus(endian, 0));
+    let fields = match header.deserialize::<Header>() {
+        Ok(((.., fields), len)) if len == 16 + fields_len => fields,
+        _ => return None,
+    };
+    if fields.iter().any(|(code, _)| *code == UNIX_FDS_FIELD) {
+        return None;
+    }
+
+    let data = Data::new(bytes.to_vec(), Context::new_dbus(endian, 0));
+    // SAFETY: the message carries no file descriptors, as checked above, and its header
+    // fields and body fill it as its header declares. A corrupt field or body fails to
+    // deserialize like any other malformed message.
+    Some(unsafe { Message::from_bytes(data) })
+}
+
+fn read_u32(bytes: &[u8], at: usize, endian: Endian) -> Option<u32> {
+    let bytes = *bytes.get(at..)?.first_chunk()?;
+    Some(match endian {
+        Endian::Little => u32::from_le_bytes(bytes),
+        Endian::Big => u32::from_be_bytes(bytes),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::counters::{Counter, CounterStats};
+    use atspi::{
+        State,
+        events::{mouse::AbsEvent, object::StateChangedEvent},
+    };
+
+    fn count(counters: &Counter, interface: &str, member: &str) -> u64 {
+        counters
+            .child(interface)
+            .and_then(|iface| iface.child(member))
+            .map_or(0, |member| member.total())
+    }
+
+    // Two signals: a focused state change and a mouse move.
+    fn packets() -> Vec<Vec<u8>> {
+        let state: Message = StateChangedEvent {
+            state: State::Focused,
+            enabled: true,
+            ..Default::default()
+        }
+        .try_into()
+        .unwrap();
+        let mouse: Message = AbsEvent::default().try_into().unwrap();
+        [state, mouse]
+            .iter()
+            .map(|msg| msg.data().bytes().to_vec())
+            .collect()
+    }
+
+    fn padded(bytes: &[u8]) -> Vec<u8> {
+        let mut bytes = bytes.to_vec();
+        bytes.resize(bytes.len().next_multiple_of(4), 0);
+        bytes
+    }
+
+    // A block of a big-endian pcapng section, its length before and after `body`.
+    fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let len = (12 + body.len()) as u32;
+        let mut block = block_type.to_be_bytes().to_vec();
+        block.extend(len.to_be_bytes());
+        block.extend(body);
+        block.extend(len.to_be_bytes());
+        block
+    }
+
+    #[test]
+    fn pcap_packets_are_counted_until_a_truncated_one() {
+        let mut bytes = Vec::new();
+        for field in [PCAP_MAGIC[0], 0x0004_0002, 0, 0, 65535, LINKTYPE_DBUS] {
+            bytes.extend(field.to_le_bytes());
+        }
+        for packet in packets() {
+            let len = packet.len() as u32;
+            for field in [1_700_000_000, 0, len, len] {
+                bytes.extend(field.to_le_bytes());
+            }
+            bytes.extend(packet);
+        }
+        // A packet header that promises more than the file holds.
+        for field in [1_700_000_000u32, 0, 100, 100] {
+            bytes.extend(field.to_le_bytes());
+        }
+        bytes.extend([b'l'; 10]);
+
+        let mut session = Session::new(false);
+        let imported = import_pcap(&mut session, &bytes, Endian::Little).unwrap();
+        assert_eq!((imported.signals, imported.skipped), (2, 1));
+        let counters = session.counters();
+        assert_eq!(count(counters, "object", "state-changed"), 1);
+        assert_eq!(count(counters, "mouse", "abs"), 1);
+        let focused = counters
+            .child("object")
+            .and_then(|iface| iface.child("state-changed"))
+            .and_then(|member| member.child("focused"))
+            .unwrap();
+        assert_eq!(focused.total(), 1);
+    }
+
+    #[test]
+    fn big_endian_pcapng_blocks_are_counted() {
+        let [state, mouse] = &packets()[..] else {
+            unreachable!()
+        };
+
+        let mut section = BYTE_ORDER_MAGIC.to_be_bytes().to_vec();
+        section.extend([0, 1, 0, 0]);
+        section.extend(u64::MAX.to_be_bytes());
+        let mut interface = (LINKTYPE_DBUS as u16).to_be_bytes().to_vec();
+        interface.extend([0, 0]);
+        interface.extend(65535u32.to_be_bytes());
+        let mut enhanced = Vec::new();
+        for field in [0, 0, 0, state.len() as u32, state.len() as u32] {
+            enhanced.extend(field.to_be_bytes());
+        }
+        enhanced.extend(padded(state));
+        let mut simple = (mouse.len() as u32).to_be_bytes().to_vec();
+        simple.extend(padded(mouse));
+
+        let mut bytes = block(SECTION_HEADER_BLOCK, &section);
+        bytes.extend(block(INTERFACE_DESCRIPTION_BLOCK, &interface));
+        bytes.extend(block(ENHANCED_PACKET_BLOCK, &enhanced));
+        bytes.extend(block(SIMPLE_PACKET_BLOCK, &simple));
+
+        let mut session = Session::new(false);
+        let imported = import_pcapng(&mut session, &bytes).unwrap();
+        assert_eq!((imported.signals, imported.skipped), (2, 0));
+        assert_eq!(count(session.counters(), "object", "state-changed"), 1);
+        assert_eq!(count(session.counters(), "mouse", "abs"), 1);
+
+        // Cut off in the middle of the last block.
+        let mut session = Session::new(false);
+        let imported = import_pcapng(&mut session, &bytes[..bytes.len() - 8]).unwrap();
+        assert_eq!((imported.signals, imported.skipped), (1, 1));
+    }
+
+    #[test]
+    fn monitor_text_is_counted_with_and_without_time() {
+        let text = "\
+signal time=1700000000.123456 sender=:1.42 -> destination=(null destination) serial=7 \
+path=/org/a11y/atspi/accessible/12; interface=org.a11y.atspi.Event.Object; member=StateChanged
+   string \"focused\"
+signal sender=:1.43 -> dest=(null destination) serial=8 \
+path=/org/a11y/atspi/accessible/root; interface=org.a11y.atspi.Event.Mouse; member=Abs
+signal time=1700000000.2 sender=org.freedesktop.DBus -> destination=:1.42 serial=2 \
+path=/org/freedesktop/DBus; interface=org.freedesktop.DBus; member=NameAcquired
+";
+
+        let mut session = Session::new(false);
+        let imported = import_monitor_text(&mut session, text);
+        assert_eq!((imported.signals, imported.skipped), (2, 0));
+        assert_eq!(count(session.counters(), "object", "state-changed"), 1);
+        assert_eq!(count(session.counters(), "mouse", "abs"), 1);
+    }
+
+    #[test]
+    fn corrupt_messages_are_rejected() {
+        let state = packets().remove(0);
+        assert!(matches!(message_from_bytes(&state), Some(Ok(_))));
+
+        // Cut off within the fixed header, then within the header fields.
+        assert!(message_from_bytes(&state[..10]).is_none());
+        assert!(message_from_bytes(&state[..20]).is_none());
+
+        // Header fields that claim more bytes than there are, even with the body length
+        // lowered to make up for them.
+        let mut long_fields = state.clone();
+        let fields_len = read_u32(&state, 12, Endian::Little).unwrap();
+        let body_len = read_u32(&state, 4, Endian::Little).unwrap();
+        long_fields[12..16].copy_from_slice(&(fields_len + 8).to_le_bytes());
+        assert!(message_from_bytes(&long_fields).is_none());
+        long_fields[4..8].copy_from_slice(&(body_len - 8).to_le_bytes());
+        assert!(message_from_bytes(&long_fields).is_none());
+        long_fields[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(message_from_bytes(&long_fields).is_none());
+
+        // An unknown endianness and an unknown protocol version.
+        let mut endian = state.clone();
+        endian[0] = b'x';
+        assert!(message_from_bytes(&endian).is_none());
+        let mut version = state.clone();
+        version[3] = 2;
+        assert!(message_from_bytes(&version).is_none());
+    }
+
+    #[test]
+    fn messages_with_file_descriptors_are_rejected() {
+        let state = packets().remove(0);
+        let context = Context::new_dbus(Endian::Little, 0);
+        let fields_len = read_u32(&state, 12, Endian::Little).unwrap() as usize;
+        let header_len = (16 + fields_len).next_multiple_of(8);
+        let data = Data::new(&state[..header_len], context);
+        let (mut header, _) = data.deserialize::<Header>().unwrap();
+        let with_header = |header: &Header| {
+            let mut bytes = zbus::zvariant::to_bytes(context, header).unwrap().to_vec();
+            bytes.resize(bytes.len().next_multiple_of(8), 0);
+            bytes.extend(&state[header_len..]);
+            bytes
+        };
+        assert!(matches!(
+            message_from_bytes(&with_header(&header)),
+            Some(Ok(_))
+        ));
+
+        header.6.push((UNIX_FDS_FIELD, Value::U32(1)));
+        assert!(message_from_bytes(&with_header(&header)).is_none());
+    }
+}
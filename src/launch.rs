@@ -0,0 +1,235 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+    time::Duration,
+};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+use zbus::{
+    Connection, MatchRule, Message,
+    fdo::{DBusProxy, NameOwnerChanged},
+    message::Type,
+};
+
+use crate::apps::{RESOLVE_TIMEOUT, process_id};
+
+/// The bus daemon, as the sender of its own signals.
+const BUS_DAEMON: &str = "org.freedesktop.DBus";
+
+/// How often a sender is looked up before its process is taken as unknown.
+const MAX_LOOKUPS: u32 = 3;
+
+/// What is known of a sender.
+enum Lookup {
+    /// Whether it is in the tree.
+    Done(bool),
+    /// Its process could not be found: it left the bus first, or the bus daemon didn't answer.
+    Unknown,
+    /// Still being looked up, for the given time.
+    Pending(u32),
+}
+
+/// A message taken by the tree, given back in the order it was received once its sender is
+/// known.
+pub enum Released {
+    /// Sent by a process in the tree.
+    InTree(Message),
+    /// Sent by a connection whose process could not be found.
+    Unknown(Message),
+}
+
+/// The bus connections of a command's process tree: of the command and of every process it
+/// started, directly or not.
+///
+/// Each sender is looked up once, when it joins the bus or else when it first sends a message,
+/// by the process ID the bus daemon has for its connection. The lookup runs in the background,
+/// so that the messages keep being read meanwhile; from the first message of a sender still
+/// being looked up, all messages are held, so that they are counted in the order they came.
+pub struct ProcessTree {
+    root: u32,
+    connection: Connection,
+    senders: HashMap<String, Lookup>,
+    /// The messages received since the first one whose sender is still being looked up.
+    held: VecDeque<Message>,
+    // Each lookup sends its sender, and whether it is in the tree or `None` if its process
+    // could not be found.
+    results: UnboundedSender<(String, Option<bool>)>,
+    lookups: UnboundedReceiver<(String, Option<bool>)>,
+}
+
+impl ProcessTree {
+    /// The tree of process `root`, with the bus daemon to ask on `connection`.
+    pub fn new(root: u32, connection: Connection) -> Self {
+        let (results, lookups) = unbounded_channel();
+        ProcessTree {
+            root,
+            connection,
+            senders: HashMap::new(),
+            held: VecDeque::new(),
+            results,
+            lookups,
+        }
+    }
+
+    /// Take `msg`, and give back the messages whose senders are known by now, in order.
+    pub fn sent(&mut self, msg: Message) -> Vec<Released> {
+        let Some(sender) = msg.header().sender().map(|name| name.to_string()) else {
+            return Vec::new();
+        };
+        if sender == BUS_DAEMON {
+            self.joined(&msg);
+            return Vec::new();
+        }
+
+        if !self.senders.contains_key(&sender) {
+            self.look_up(sender);
+        }
+        self.held.push_back(msg);
+        self.release()
+    }
+
+    // Start looking up a connection as soon as it joins the bus, so that its process is still
+    // found if it leaves before its messages are read.
+    fn joined(&mut self, msg: &Message) {
+        let Some(signal) = NameOwnerChanged::from_message(msg.clone()) else {
+            return;
+        };
+        let Ok(args) = signal.args() else {
+            return;
+        };
+        let name = args.name().to_string();
+        if name.starts_with(':') && args.new_owner().is_some() && !self.senders.contains_key(&name)
+        {
+            self.look_up(name);
+        }
+    }
+
+    fn look_up(&mut self, sender: String) {
+        let lookups = match self.senders.get(&sender) {
+            Some(Lookup::Pending(lookups)) => lookups + 1,
+            _ => 1,
+        };
+        self.senders
+            .insert(sender.clone(), Lookup::Pending(lookups));
+
+        let connection = self.connection.clone();
+        let root = self.root;
+        let results = self.results.clone();
+        tokio::spawn(async move {
+            let pid = tokio::time::timeout(RESOLVE_TIMEOUT, process_id(&connection, &sender)).await;
+            let in_tree = match pid {
+                Ok(Ok(pid)) => Some(descends_from(pid, root)),
+                Ok(Err(_)) | Err(_) => None,
+            };
+            // The tree is only dropped when the session ends.
+            let _ = results.send((sender, in_tree));
+        });
+    }
+
+    // The held messages up to the first one whose sender is still being looked up: those of
+    // the tree and those of unknown processes, in order.
+    fn release(&mut self) -> Vec<Released> {
+        let mut released = Vec::new();
+        while let Some(msg) = self.held.front() {
+            let in_tree = match msg
+                .header()
+                .sender()
+                .and_then(|sender| self.senders.get(sender.as_str()))
+            {
+                Some(Lookup::Pending(_)) => break,
+                Some(Lookup::Done(in_tree)) => Some(*in_tree),
+                Some(Lookup::Unknown) | None => None,
+            };
+            let Some(msg) = self.held.pop_front() else {
+                break;
+            };
+            match in_tree {
+                Some(true) => released.push(Released::InTree(msg)),
+                Some(false) => {}
+                None => released.push(Released::Unknown(msg)),
+            }
+        }
+        released
+    }
+
+    /// Wait for the lookup of a sender to finish, and give back the messages whose senders are
+    /// known by now, in order. A sender whose process wasn't found is looked up again, up to
+    /// `MAX_LOOKUPS` times.
+    ///
+    /// Cancel safe, for `select!`.
+    pub async fn looked_up(&mut self) -> Vec<Released> {
+        let Some((sender, in_tree)) = self.lookups.recv().await else {
+            return std::future::pending().await;
+        };
+        match in_tree {
+            Some(in_tree) => {
+                self.senders.insert(sender, Lookup::Done(in_tree));
+            }
+            None => match self.senders.get(&sender) {
+                Some(Lookup::Pending(lookups)) if *lookups < MAX_LOOKUPS => self.look_up(sender),
+                _ => {
+                    self.senders.insert(sender, Lookup::Unknown);
+                }
+            },
+        }
+        self.release()
+    }
+
+    /// Wait up to `limit` for the senders of the held messages to be looked up, then give back
+    /// all of them, those of senders still not found as unknown.
+    pub async fn finish(&mut self, limit: Duration) -> Vec<Released> {
+        let mut released = Vec::new();
+        let _ = tokio::time::timeout(limit, async {
+            while !self.held.is_empty() {
+                released.extend(self.looked_up().await);
+            }
+        })
+        .await;
+
+        for lookup in self.senders.values_mut() {
+            if let Lookup::Pending(_) = lookup {
+                *lookup = Lookup::Unknown;
+            }
+        }
+        released.extend(self.release());
+        released
+    }
+}
+
+/// Subscribe `connection` to the bus daemon's signal for connections that join or leave the
+/// bus, so that the connections of a command are looked up as they join.
+pub async fn add_match_rule(connection: &Connection) -> zbus::Result<()> {
+    let rule = MatchRule::builder()
+        .msg_type(Type::Signal)
+        .sender(BUS_DAEMON)?
+        .interface(BUS_DAEMON)?
+        .member("NameOwnerChanged")?
+        .build();
+    DBusProxy::new(connection)
+        .await?
+        .add_match_rule(rule)
+        .await?;
+    Ok(())
+}
+
+// Whether process `pid` is `root` or one of its descendants, by its chain of parents.
+// A process whose parent exited has been adopted by another process, and is lost to the tree.
+fn descends_from(mut pid: u32, root: u32) -> bool {
+    loop {
+        if pid == root {
+            return true;
+        }
+        match parent(pid) {
+            Some(parent) if parent != 0 && parent != pid => pid = parent,
+            _ => return false,
+        }
+    }
+}
+
+// The parent of process `pid`, from `/proc/<pid>/stat`: "<pid> (<comm>) <state> <ppid> ...".
+// The command name may hold spaces and parentheses of its own, so it is skipped by the last ')'.
+fn parent(pid: u32) -> Option<u32> {
+    let stat =
+        std::fs::read_to_string(Path::new("/proc").join(pid.to_string()).join("stat")).ok()?;
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(1)?.parse().ok()
+}
@@ -0,0 +1,476 @@
+use atspi::events::{
+    AvailableEvent, BusProperties, CacheEvents, DocumentEvents, EventListenerEvents, FocusEvents,
+    HasInterfaceName, KeyboardEvents, MouseEvents, ObjectEvents, TerminalEvents, WindowEvents,
+};
+use chrono::{DateTime, Local, NaiveDateTime};
+use std::{error::Error, path::Path};
+
+use crate::categories::{INTERFACES, interface_name};
+use crate::stats_file::{CounterNode, StatsFile};
+
+// A legacy interface: its legacy name, its D-Bus interface and its members in order,
+// with the atspi event type that was counted under each legacy name.
+type LegacyInterface = (
+    &'static str,
+    &'static str,
+    &'static [(&'static str, &'static str)],
+);
+
+// The counters as named by legacy `.txt` files, when the names were typed by hand.
+const LEGACY_INTERFACES: [LegacyInterface; 10] = [
+    (
+        "object",
+        ObjectEvents::DBUS_INTERFACE,
+        &[
+            ("property-change", "PropertyChangeEvent"),
+            ("bounds-changed", "BoundsChangedEvent"),
+            ("link-selected", "LinkSelectedEvent"),
+            ("state-changed", "StateChangedEvent"),
+            ("children-changed", "ChildrenChangedEvent"),
+            ("visible-data-changed", "VisibleDataChangedEvent"),
+            ("selection-changed", "SelectionChangedEvent"),
+            ("model-changed", "ModelChangedEvent"),
+            ("active-descendant-changed", "ActiveDescendantChangedEvent"),
+            ("announcement", "AnnouncementEvent"),
+            ("attributes-changed", "AttributesChangedEvent"),
+            ("row-inserted", "RowInsertedEvent"),
+            ("row-reordered", "RowReorderedEvent"),
+            ("row-deleted", "RowDeletedEvent"),
+            ("column-inserted", "ColumnInsertedEvent"),
+            ("column-reordered", "ColumnReorderedEvent"),
+            ("column-deleted", "ColumnDeletedEvent"),
+            ("text-bounds-changed", "TextBoundsChangedEvent"),
+            ("text-selection-changed", "TextSelectionChangedEvent"),
+            ("text-changed", "TextChangedEvent"),
+            ("text-attributes-changed", "TextAttributesChangedEvent"),
+            ("text-caret-moved", "TextCaretMovedEvent"),
+        ],
+    ),
+    (
+        "window",
+        WindowEvents::DBUS_INTERFACE,
+        &[
+            ("property-change", "PropertyChangeEvent"),
+            ("minimize", "MinimizeEvent"),
+            ("maximize", "MaximizeEvent"),
+            ("restore", "RestoreEvent"),
+            ("close", "CloseEvent"),
+            ("create", "CreateEvent"),
+            ("reparent", "ReparentEvent"),
+            ("desktop-create", "DesktopCreateEvent"),
+            ("desktop-destroy", "DesktopDestroyEvent"),
+            ("destroy", "DestroyEvent"),
+            ("activate", "ActivateEvent"),
+            ("deactivate", "DeactivateEvent"),
+            ("raise", "RaiseEvent"),
+            ("lower", "LowerEvent"),
+            ("move", "MoveEvent"),
+            ("resize", "ResizeEvent"),
+            ("shade", "ShadeEvent"),
+            ("uushade", "UUshadeEvent"),
+            ("restyle", "RestyleEvent"),
+        ],
+    ),
+    (
+        "document",
+        DocumentEvents::DBUS_INTERFACE,
+        &[
+            ("load-complete", "LoadCompleteEvent"),
+            ("reload", "ReloadEvent"),
+            ("load-stopped", "LoadStoppedEvent"),
+            ("content-changed", "ContentChangedEvent"),
+            ("attributes-changed", "AttributesChangedEvent"),
+            ("page-changed", "PageChangedEvent"),
+        ],
+    ),
+    (
+        "terminal",
+        TerminalEvents::DBUS_INTERFACE,
+        &[
+            ("line-changed", "LineChangedEvent"),
+            ("column-count-changed", "ColumnCountChangedEvent"),
+            ("line-count-changed", "LineCountChangedEvent"),
+            ("application-changed", "ApplicationChangedEvent"),
+            ("char-width-changed", "CharWidthChangedEvent"),
+        ],
+    ),
+    (
+        "mouse",
+        MouseEvents::DBUS_INTERFACE,
+        &[
+            ("abs", "AbsEvent"),
+            ("rel", "RelEvent"),
+            ("button", "ButtonEvent"),
+        ],
+    ),
+    (
+        "keyboard",
+        KeyboardEvents::DBUS_INTERFACE,
+        &[("modifiers", "ModifiersEvent")],
+    ),
+    (
+        "listener",
+        EventListenerEvents::DBUS_INTERFACE,
+        &[
+            ("registered", "EventListenerRegisteredEvent"),
+            ("deregistered", "EventListenerDeregisteredEvent"),
+        ],
+    ),
+    (
+        "cache",
+        CacheEvents::DBUS_INTERFACE,
+        &[
+            ("add", "AddAccessibleEvent"),
+            ("legacy-add", "LegacyAddAccessibleEvent"),
+            ("remove", "RemoveAccessibleEvent"),
+        ],
+    ),
+    (
+        "focus",
+        FocusEvents::DBUS_INTERFACE,
+        &[("focus", "FocusEvent")],
+    ),
+    (
+        "available",
+        <AvailableEvent as BusProperties>::DBUS_INTERFACE,
+        &[("available", "AvailableEvent")],
+    ),
+];
+
+// The counters in a legacy `.txt` file, in order: the interface totals, then the members of
+// these interfaces.
+const LEGACY_BLOCKS: [&str; 7] = [
+    "interface",
+    "object",
+    "window",
+    "terminal",
+    "document",
+    "mouse",
+    "cache",
+];
+
+fn legacy_interface(name: &str) -> Option<&'static LegacyInterface> {
+    LEGACY_INTERFACES
+        .iter()
+        .find(|(legacy, _, _)| *legacy == name)
+}
+
+// Rename a counter tree that uses the hand-typed names of older versions to the names
+// derived from atspi's metadata.
+fn upgrade_legacy_names(mut counters: CounterNode) -> Result<CounterNode, Box<dyn Error>> {
+    for iface in &mut counters.children {
+        let (_, dbus_interface, members) = legacy_interface(&iface.name)
+            .ok_or_else(|| format!("unknown legacy interface {:?}", iface.name))?;
+        let categories = INTERFACES
+            .iter()
+            .find(|categories| categories[0].interface == *dbus_interface)
+            .ok_or_else(|| format!("atspi no longer has interface {dbus_interface}"))?;
+
+        for member in &mut iface.children {
+            let (_, event_type) = members
+                .iter()
+                .find(|(legacy, _)| *legacy == member.name)
+                .ok_or_else(|| {
+                    format!("unknown legacy {} category {:?}", iface.name, member.name)
+                })?;
+            let category = categories
+                .iter()
+                .find(|cat| cat.event_type.rsplit("::").next() == Some(*event_type))
+                .ok_or_else(|| format!("atspi no longer has event type {event_type}"))?;
+            member.name = category.name();
+        }
+
+        iface.name = interface_name(dbus_interface);
+    }
+
+    Ok(counters)
+}
+
+// Legacy `.txt` stats files hold one anonymous block per counter, in the order of `LEGACY_BLOCKS`:
+//
+// Total events: <total>
+// <category>: <count>,<category>: <count>,...
+//
+// The blocks carry no counter name, so they can only be interpreted by position.
+// That makes checking the category names against the legacy layout our only guard against
+// reading a file with a different layout.
+
+/// Read a legacy `atspi-stats-<timestamp>.txt` file into the current stats file format.
+pub fn read_legacy(path: &Path) -> Result<StatsFile, Box<dyn Error>> {
+    let contents = std::fs::read_to_string(path)?;
+    let mut lines = contents.lines();
+    let mut counters = CounterNode {
+        name: "interface".to_string(),
+        count: 0,
+        saturated: false,
+        children: Vec::new(),
+    };
+
+    for block in LEGACY_BLOCKS {
+        let expected: Vec<&str> = match legacy_interface(block) {
+            Some((_, _, members)) => members.iter().map(|(name, _)| *name).collect(),
+            None => LEGACY_INTERFACES.iter().map(|(name, _, _)| *name).collect(),
+        };
+
+        let total_line = lines
+            .next()
+            .ok_or_else(|| format!("{}: unexpected end of file", path.display()))?;
+        // Legacy files were written with 32-bit counters.
+        let total: u64 = total_line
+            .strip_prefix("Total events: ")
+            .ok_or_else(|| format!("{}: expected total, got {total_line:?}", path.display()))?
+            .trim()
+            .parse::<u32>()?
+            .into();
+
+        let categories_line = lines
+            .next()
+            .ok_or_else(|| format!("{}: unexpected end of file", path.display()))?;
+        let entries: Vec<&str> = categories_line
+            .split(',')
+            .filter(|entry| !entry.is_empty())
+            .collect();
+
+        if entries.len() != expected.len() {
+            return Err(format!(
+                "{}: expected {} {block} categories, found {}",
+                path.display(),
+                expected.len(),
+                entries.len()
+            )
+            .into());
+        }
+
+        let mut nodes = Vec::new();
+        let mut sum = 0;
+        for (entry, expected_cat) in entries.iter().zip(expected) {
+            let (category, count) = entry
+                .split_once(": ")
+                .ok_or_else(|| format!("{}: malformed category {entry:?}", path.display()))?;
+            if category != expected_cat {
+                return Err(format!(
+                    "{}: expected {block} category {expected_cat:?}, found {category:?}",
+                    path.display(),
+                )
+                .into());
+            }
+            let count: u64 = count.trim().parse::<u32>()?.into();
+            nodes.push(CounterNode {
+                name: category.to_string(),
+                count,
+                saturated: false,
+                children: Vec::new(),
+            });
+            sum += count;
+        }
+
+        // The total and the categories were read one after the other while events were still
+        // being counted, so they may be a few events apart. The category names are the check.
+        if sum != total {
+            eprintln!(
+                "{}: {block} total is {total}, but its categories add up to {sum}",
+                path.display(),
+            );
+        }
+
+        // The interface block comes first, so the member blocks can be attached to it.
+        match counters
+            .children
+            .iter_mut()
+            .find(|iface| iface.name == block)
+        {
+            Some(iface) => iface.children = nodes,
+            None => {
+                counters.count = total;
+                counters.children = nodes;
+            }
+        }
+    }
+
+    if let Some(line) = lines.find(|line| !line.trim().is_empty()) {
+        return Err(format!("{}: unexpected trailing line {line:?}", path.display()).into());
+    }
+
+    // The session start was never recorded; the timestamp in the file name is when the
+    // file was written, at the end of the session.
+    let session_end = session_end_from_filename(path)
+        .or_else(|| {
+            std::fs::metadata(path)
+                .ok()?
+                .modified()
+                .ok()
+                .map(Into::into)
+        })
+        .unwrap_or_else(Local::now);
+
+    Ok(StatsFile::from_counter_node(
+        upgrade_legacy_names(counters)?,
+        None,
+        session_end,
+    ))
+}
+
+fn session_end_from_filename(path: &Path) -> Option<DateTime<Local>> {
+    let timestamp = path.file_stem()?.to_str()?.strip_prefix("atspi-stats-")?;
+    NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d-%H-%M-%S")
+        .ok()?
+        .and_local_timezone(Local)
+        .earliest()
+}
+
+/// Convert legacy `.txt` stats files to `.json` stats files next to them.
+pub fn migrate(files: &[impl AsRef<Path>]) -> Result<(), Box<dyn Error>> {
+    for file in files {
+        let file = file.as_ref();
+        let target = file.with_extension("json");
+        if target.exists() {
+            return Err(format!("{}: already exists", target.display()).into());
+        }
+
+        read_legacy(file)?.write(&target)?;
+        println!("{} -> {}", file.display(), target.display());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::counters::{CounterStats, new_counter_tree};
+
+    // A file as the baseline wrote it, at the end of a session.
+    const BASELINE: &str = "\
+Total events: 364
+object: 133,window: 109,document: 38,terminal: 32,mouse: 16,keyboard: 9,listener: 3,cache: 14,focus: 8,available: 2,
+Total events: 133
+property-change: 7,bounds-changed: 1,link-selected: 8,state-changed: 2,children-changed: 9,visible-data-changed: 3,selection-changed: 10,model-changed: 4,active-descendant-changed: 11,announcement: 5,attributes-changed: 12,row-inserted: 6,row-reordered: 0,row-deleted: 7,column-inserted: 1,column-reordered: 8,column-deleted: 2,text-bounds-changed: 9,text-selection-changed: 3,text-changed: 10,text-attributes-changed: 4,text-caret-moved: 11,
+Total events: 109
+property-change: 5,minimize: 12,maximize: 6,restore: 0,close: 7,create: 1,reparent: 8,desktop-create: 2,desktop-destroy: 9,destroy: 3,activate: 10,deactivate: 4,raise: 11,lower: 5,move: 12,resize: 6,shade: 0,uushade: 7,restyle: 1,
+Total events: 32
+line-changed: 8,column-count-changed: 2,line-count-changed: 9,application-changed: 3,char-width-changed: 10,
+Total events: 38
+load-complete: 4,reload: 11,load-stopped: 5,content-changed: 12,attributes-changed: 6,page-changed: 0,
+Total events: 16
+abs: 7,rel: 1,button: 8,
+Total events: 14
+add: 2,legacy-add: 9,remove: 3,
+";
+
+    // Read `contents` as a legacy file of its own.
+    fn read(name: &str, contents: &str) -> Result<StatsFile, Box<dyn Error>> {
+        let dir = std::env::temp_dir().join(format!(
+            "atspi-counters-legacy-{name}-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("atspi-stats-2024-01-02-03-04-05.txt");
+        std::fs::write(&path, contents).unwrap();
+        let stats = read_legacy(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+        stats
+    }
+
+    fn error(name: &str, contents: &str) -> String {
+        match read(name, contents) {
+            Ok(_) => panic!("{name}: read without error"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn baseline_file_is_read_with_current_names() {
+        let stats = read("baseline", BASELINE).unwrap();
+        assert_eq!(stats.session_start, None);
+        assert_eq!(
+            stats.session_end.naive_local().to_string(),
+            "2024-01-02 03:04:05"
+        );
+
+        let counters = new_counter_tree();
+        stats.counters.add_to(&counters).unwrap();
+        assert_eq!(counters.total(), 364);
+        let count = |path: &[&str]| {
+            path.iter()
+                .try_fold(&counters, |counter, name| counter.child(name))
+                .unwrap()
+                .total()
+        };
+        assert_eq!(count(&["object", "state-changed"]), 2);
+        assert_eq!(count(&["window", "u-ushade"]), 7);
+        assert_eq!(count(&["terminal", "columncount-changed"]), 2);
+        assert_eq!(count(&["cache", "legacy-add-accessible"]), 9);
+        assert_eq!(count(&["registry"]), 3);
+        assert_eq!(count(&["socket"]), 2);
+    }
+
+    #[test]
+    fn unknown_category_is_rejected() {
+        let contents = BASELINE.replace("abs: 7", "absolute: 7");
+        assert!(
+            error("unknown", &contents).contains("expected mouse category \"abs\""),
+            "{contents}"
+        );
+    }
+
+    #[test]
+    fn totals_off_from_their_categories_are_read() {
+        // The interface block has the count of each interface; the totals of the member
+        // blocks are only checked against their categories.
+        let contents = BASELINE
+            .replace("Total events: 364\n", "Total events: 365\n")
+            .replace("Total events: 16\n", "Total events: 17\n");
+        let stats = read("totals", &contents).unwrap();
+        assert_eq!(stats.counters.count, 365);
+        let mouse = stats
+            .counters
+            .children
+            .iter()
+            .find(|iface| iface.name == "mouse")
+            .unwrap();
+        assert_eq!(mouse.count, 16);
+    }
+
+    #[test]
+    fn missing_and_trailing_blocks_are_rejected() {
+        let cut = BASELINE.lines().take(12).collect::<Vec<_>>().join("\n");
+        assert!(error("missing", &cut).contains("unexpected end of file"));
+
+        let trailing = format!("{BASELINE}Total events: 0\nfoo: 0,\n");
+        assert!(error("trailing", &trailing).contains("unexpected trailing line"));
+    }
+
+    #[test]
+    fn count_over_u32_is_rejected() {
+        let contents = BASELINE.replace("rel: 1,", "rel: 4294967296,");
+        error("overflow", &contents);
+    }
+
+    fn node(name: &str, children: Vec<CounterNode>) -> CounterNode {
+        CounterNode {
+            name: name.to_string(),
+            count: 0,
+            saturated: false,
+            children,
+        }
+    }
+
+    #[test]
+    fn legacy_names_upgrade_to_the_counter_tree() {
+        let legacy = node(
+            "interface",
+            LEGACY_INTERFACES
+                .iter()
+                .map(|(name, _, members)| {
+                    node(
+                        name,
+                        members.iter().map(|(name, _)| node(name, vec![])).collect(),
+                    )
+                })
+                .collect(),
+        );
+
+        let upgraded = upgrade_legacy_names(legacy).unwrap();
+        upgraded.add_to(&new_counter_tree()).unwrap();
+    }
+}
@@ -0,0 +1,112 @@
+use atspi::{
+    events::{EventListenerEvents, EventListeners},
+    proxy::registry::RegistryProxy,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use zbus::Connection;
+
+/// How often listeners for one event name, or of one client, were registered and deregistered.
+#[derive(Debug, Clone, Copy, Default)]
+struct Changes {
+    registered: u64,
+    deregistered: u64,
+}
+
+/// The event listeners that clients, like screen readers, register with the registry:
+/// which client listens for which events, e.g. `object:state-changed`, and for how long.
+#[derive(Default)]
+pub struct Listeners {
+    events: BTreeMap<String, Changes>,
+    clients: BTreeMap<String, Changes>,
+    /// The registrations that were not deregistered, by client bus name and event name.
+    active: BTreeSet<(String, String)>,
+}
+
+impl Listeners {
+    pub fn new() -> Self {
+        Listeners::default()
+    }
+
+    pub fn record(&mut self, lev: &EventListenerEvents) {
+        match lev {
+            EventListenerEvents::Registered(ev) => {
+                let listener = &ev.registered_event;
+                self.changes(listener, |changes| &mut changes.registered);
+                self.active.insert(key(listener));
+            }
+            EventListenerEvents::Deregistered(ev) => {
+                let listener = &ev.deregistered_event;
+                self.changes(listener, |changes| &mut changes.deregistered);
+                self.active.remove(&key(listener));
+            }
+        }
+    }
+
+    // Count a change of `listener` by its event name and by its client.
+    fn changes(&mut self, listener: &EventListeners, count: impl Fn(&mut Changes) -> &mut u64) {
+        let (client, event) = key(listener);
+        for changes in [
+            self.events.entry(event).or_default(),
+            self.clients.entry(client).or_default(),
+        ] {
+            let count = count(changes);
+            *count = count.saturating_add(1);
+        }
+    }
+
+    /// Take the registrations the registry has on `connection` as active, other than those of
+    /// `connection` itself, so that listeners registered before the session are known too.
+    pub async fn registered_on(&mut self, connection: &Connection) -> zbus::Result<()> {
+        let registered = RegistryProxy::new(connection)
+            .await?
+            .registered_events()
+            .await?;
+        let own = connection.unique_name().map(|name| name.as_str());
+
+        for (client, event) in registered {
+            if Some(client.as_str()) != own {
+                self.active.insert((client.to_string(), event));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty() && self.active.is_empty()
+    }
+
+    /// Print the registrations and deregistrations by event name and by client, then the
+    /// registrations still active.
+    pub fn print(&self) {
+        if self.is_empty() {
+            return;
+        }
+
+        println!();
+        println!("Listener stats:");
+        print_table("event", &self.events);
+        println!();
+        print_table("client", &self.clients);
+
+        println!();
+        println!("Active listeners: {}", self.active.len());
+        for (client, event) in &self.active {
+            println!("{:<30}{}", client, event);
+        }
+    }
+}
+
+// A listener by its client bus name and event name.
+fn key(listener: &EventListeners) -> (String, String) {
+    (listener.bus_name.to_string(), listener.path.clone())
+}
+
+fn print_table(heading: &str, rows: &BTreeMap<String, Changes>) {
+    println!("{:<50}{:<14}deregistered", heading, "registered");
+    for (name, changes) in rows {
+        println!(
+            "{:<50}{:<14}{}",
+            name, changes.registered, changes.deregistered
+        );
+    }
+}
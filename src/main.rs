@@ -1,131 +1,148 @@
-use atspi::Event;
-use std::{error::Error, sync::Arc};
-use tokio_stream::StreamExt;
+use std::{error::Error, path::PathBuf, process::ExitCode};
+mod categories;
+mod codegen;
 mod counters;
-use clap::Parser;
-use counters::{
-    CacheCount, CounterStats, DocumentCount, InterfaceCount, MouseCount, ObjectCount,
-    TerminalCount, WindowCount,
-};
+mod details;
+mod emit;
+use clap::{ArgGroup, Parser};
 
 mod aggregate;
-mod writer;
-use writer::write_stats;
+mod apps;
+#[cfg(test)]
+mod bench;
+mod capture;
+mod import;
+mod launch;
+mod legacy;
+mod listeners;
 mod matchers;
-use matchers::*;
+mod raw;
+mod recommend;
+mod session;
 mod setup;
-use setup::*;
+mod stats_file;
+mod unparsed;
+mod writer;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
+// Without a mode, a live session is run; only one mode can be given.
+#[command(group(ArgGroup::new("mode").multiple(false)))]
 struct Args {
     /// Aggregate the stats files and print the total stats
-    #[arg(short, long)]
+    #[arg(short, long, group = "mode")]
     aggregate: bool,
+
+    /// Convert legacy `.txt` stats files to `.json` stats files
+    #[arg(short, long, value_name = "FILE", num_args = 1.., group = "mode")]
+    migrate: Vec<PathBuf>,
+
+    /// Also tally every atspi signal by D-Bus interface, member and signature, before parsing
+    #[arg(
+        short,
+        long,
+        conflicts_with_all = ["aggregate", "migrate", "emit", "recommend", "codegen"]
+    )]
+    raw: bool,
+
+    /// Also append every signal to a new capture file, to replay later
+    #[arg(long, value_name = "FILE", conflicts_with = "mode")]
+    record: Option<PathBuf>,
+
+    /// Count the signals in a capture file instead of listening on the bus
+    #[arg(long, value_name = "FILE", group = "mode")]
+    replay: Option<PathBuf>,
+
+    /// Count the AT-SPI signals in `busctl capture` pcap files or `dbus-monitor` text dumps
+    #[arg(long, value_name = "FILE", num_args = 1.., group = "mode")]
+    import: Vec<PathBuf>,
+
+    /// Send the signals in a capture file to the bus at `--bus`, with their original timing
+    #[arg(long, value_name = "FILE", requires = "bus", group = "mode")]
+    emit: Option<PathBuf>,
+
+    /// The address of the bus to emit to, e.g. of a private `dbus-daemon`
+    #[arg(long, value_name = "ADDRESS", requires = "emit")]
+    bus: Option<String>,
+
+    /// Emit this many times faster than the capture was recorded
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0, requires = "emit")]
+    speed: f64,
+
+    /// Emit only the bursts of signals, leaving out the pauses between them
+    #[arg(long, requires = "emit")]
+    burst: bool,
+
+    /// Emit the signals as fast as possible
+    #[arg(long, requires = "emit", conflicts_with_all = ["speed", "burst"])]
+    fast: bool,
+
+    /// Recommend a match order for atspi's event parser from the stats files
+    #[arg(long, group = "mode")]
+    recommend: bool,
+
+    /// Print Rust source for atspi's event parser, ordered by the stats files
+    #[arg(long, group = "mode")]
+    codegen: bool,
+
+    /// Run this command and count only the events of its processes, until it exits
+    #[arg(last = true, value_name = "COMMAND", conflicts_with = "mode")]
+    command: Vec<String>,
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() -> Result<ExitCode, Box<dyn Error>> {
     let args = Args::parse();
-    let atspi = setup().await?;
 
     if args.aggregate {
-        todo!();
+        aggregate::aggregate()?;
+        return Ok(ExitCode::SUCCESS);
     }
 
-    let events = atspi.event_stream();
-    tokio::pin!(events);
-
-    let iface_count = Arc::new(InterfaceCount::new());
-    let obj_count = Arc::new(ObjectCount::new());
-    let win_count = Arc::new(WindowCount::new());
-    let term_count = Arc::new(TerminalCount::new());
-    let doc_count = Arc::new(DocumentCount::new());
-    let mouse_count = Arc::new(MouseCount::new());
-    let cache_count = Arc::new(CacheCount::new());
-
-    let ctrlc_iface_count = iface_count.clone();
-    let ctrlc_obj_count = obj_count.clone();
-    let ctrlc_win_count = win_count.clone();
-    let ctrlc_term_count = term_count.clone();
-    let ctrlc_doc_count = doc_count.clone();
-    let ctrlc_mouse_count = mouse_count.clone();
-    let ctrlc_cache_count = cache_count.clone();
-
-    ctrlc::set_handler(move || {
-        let collections: &[Arc<dyn CounterStats>] = &[
-            ctrlc_iface_count.clone(),
-            ctrlc_obj_count.clone(),
-            ctrlc_win_count.clone(),
-            ctrlc_term_count.clone(),
-            ctrlc_doc_count.clone(),
-            ctrlc_mouse_count.clone(),
-            ctrlc_cache_count.clone(),
-        ];
-        write_stats(collections);
-
-        println!("\n\nStats:");
-        println!("Interface stats:");
-        ctrlc_iface_count.pretty_print_stats();
-
-        println!("\nObject stats:");
-        ctrlc_obj_count.pretty_print_stats();
-
-        println!("\nWindow stats:");
-        ctrlc_win_count.pretty_print_stats();
-
-        println!("\nTerminal stats:");
-        ctrlc_term_count.pretty_print_stats();
-
-        println!("\nDocument stats:");
-        ctrlc_doc_count.pretty_print_stats();
-
-        println!("\nMouse stats:");
-        ctrlc_mouse_count.pretty_print_stats();
-
-        println!("\nCache stats:");
-        ctrlc_cache_count.pretty_print_stats();
-
-        std::process::exit(0);
-    })
-    .expect("Error setting Ctrl-C handler");
-
-    println!("Press Ctrl+C to stop the program and get stats");
-
-    while let Some(Ok(ev)) = events.next().await {
-        match ev {
-            Event::Object(objev) => {
-                iface_count.increment("object");
-                match_object_events(objev, &obj_count);
-            }
-            Event::Window(wev) => {
-                iface_count.increment("window");
-                match_windowevents(wev, &win_count);
-            }
-            Event::Document(dev) => {
-                iface_count.increment("document");
-                match_documentevents(dev, &doc_count);
-            }
-            Event::Terminal(term_ev) => {
-                iface_count.increment("terminal");
-                match_terminal_events(term_ev, &term_count);
-            }
-            Event::Mouse(mev) => {
-                iface_count.increment("mouse");
-                match_mouse_events(mev, &mouse_count);
-            }
-
-            Event::Cache(cev) => {
-                iface_count.increment("cache");
-                match_cache_events(cev, &cache_count);
-            }
-
-            Event::Keyboard(_) => iface_count.increment("keyboard"),
-            Event::Listener(_) => iface_count.increment("listener"),
-            Event::Focus(_) => iface_count.increment("focus"),
-            Event::Available(_) => iface_count.increment("available"),
-            _ => {}
+    if args.recommend {
+        recommend::recommend()?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if args.codegen {
+        codegen::codegen()?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if !args.migrate.is_empty() {
+        legacy::migrate(&args.migrate)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let (Some(capture), Some(bus)) = (&args.emit, &args.bus) {
+        if !(args.speed > 0.0 && args.speed.is_finite()) {
+            return Err(format!("invalid speed {}, expected a positive factor", args.speed).into());
         }
+        let pace = if args.fast {
+            emit::Pace::Fast
+        } else if args.burst {
+            emit::Pace::Bursts { speed: args.speed }
+        } else {
+            emit::Pace::Timed { speed: args.speed }
+        };
+        emit::emit(capture, bus, pace).await?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(capture) = &args.replay {
+        session::replay(capture, args.raw)?;
+        return Ok(ExitCode::SUCCESS);
     }
-    Ok(())
+
+    if !args.import.is_empty() {
+        import::import(&args.import, args.raw)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    session::run(session::Options {
+        raw: args.raw,
+        record: args.record,
+        command: args.command,
+    })
+    .await
 }
@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Mutex};
+use zbus::{MatchRule, Message, fdo::DBusProxy, message::Type};
+
+use crate::categories::{find_category, interface_name};
+use crate::counters::{Counter, CounterStats, new_counter_tree};
+
+/// Every accessible, the registry and the cache live under this path.
+const ATSPI_PATH: &str = "/org/a11y/atspi";
+
+/// The count of signals with one D-Bus interface, member and body signature.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawCount {
+    pub interface: String,
+    pub member: String,
+    pub signature: String,
+    pub count: u64,
+}
+
+type RawKey = (String, String, String);
+
+/// Signals tallied straight from their message headers, before atspi parses them.
+#[derive(Debug, Default)]
+pub struct RawCounts {
+    counts: Mutex<HashMap<RawKey, u64>>,
+}
+
+impl RawCounts {
+    pub fn new() -> Self {
+        RawCounts::default()
+    }
+
+    /// Count `msg` if it is a signal of an accessible, the registry or the cache.
+    pub fn record(&self, msg: &Message) {
+        let header = msg.header();
+        let in_namespace = header.path().is_some_and(|path| {
+            path.as_str() == ATSPI_PATH
+                || path
+                    .as_str()
+                    .strip_prefix(ATSPI_PATH)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        });
+        if header.message_type() != Type::Signal || !in_namespace {
+            return;
+        }
+
+        let interface = header.interface().map_or("", |name| name.as_str());
+        let member = header.member().map_or("", |name| name.as_str());
+        let signature = msg.body().signature().to_string_no_parens();
+        self.add(interface, member, &signature, 1);
+    }
+
+    pub fn add(&self, interface: &str, member: &str, signature: &str, count: u64) {
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        let total = counts
+            .entry((
+                interface.to_string(),
+                member.to_string(),
+                signature.to_string(),
+            ))
+            .or_default();
+        *total = total.saturating_add(count);
+    }
+
+    /// All counts, the most frequent first.
+    pub fn counts(&self) -> Vec<RawCount> {
+        let counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        let mut counts: Vec<RawCount> = counts
+            .iter()
+            .map(|((interface, member, signature), count)| RawCount {
+                interface: interface.clone(),
+                member: member.clone(),
+                signature: signature.clone(),
+                count: *count,
+            })
+            .collect();
+        counts.sort_by(|a, b| {
+            b.count.cmp(&a.count).then_with(|| {
+                (&a.interface, &a.member, &a.signature).cmp(&(
+                    &b.interface,
+                    &b.member,
+                    &b.signature,
+                ))
+            })
+        });
+        counts
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_empty()
+    }
+
+    /// These signals in a counter tree laid out like `new_counter_tree`,
+    /// and the signals that match no category atspi knows about.
+    pub fn counter_tree(&self) -> (Counter, Vec<RawCount>) {
+        let counters = new_counter_tree();
+        let mut unknown = Vec::new();
+
+        for count in self.counts() {
+            match find_category(&count.interface, &count.member, &count.signature) {
+                Some((_, _, category)) => counters.add(
+                    &[&interface_name(category.interface), &category.name()],
+                    count.count,
+                ),
+                None => unknown.push(count),
+            }
+        }
+
+        (counters, unknown)
+    }
+}
+
+/// Subscribe `conn` to every signal under the atspi path, whether atspi can parse it or not.
+pub async fn add_match_rule(conn: &zbus::Connection) -> zbus::Result<()> {
+    let rule = MatchRule::builder()
+        .msg_type(Type::Signal)
+        .path_namespace(ATSPI_PATH)?
+        .build();
+    DBusProxy::new(conn).await?.add_match_rule(rule).await?;
+    Ok(())
+}
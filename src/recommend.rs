@@ -0,0 +1,174 @@
+use atspi::events::{
+    AvailableEvent, BusProperties, CacheEvents, DocumentEvents, EventListenerEvents, FocusEvents,
+    HasInterfaceName, KeyboardEvents, MouseEvents, ObjectEvents, TerminalEvents, WindowEvents,
+};
+use std::error::Error;
+
+use crate::aggregate::read_stats_files;
+use crate::categories::{INTERFACES, interface_name};
+use crate::counters::{Counter, CounterStats, Getters};
+
+/// The order in which atspi matches the interface of a signal, in
+/// `impl TryFrom<&zbus::Message> for Event`.
+/// Within an interface, atspi matches the members in the order of `CATEGORIES`.
+const ATSPI_INTERFACE_ORDER: [&str; 10] = [
+    <AvailableEvent as BusProperties>::DBUS_INTERFACE,
+    ObjectEvents::DBUS_INTERFACE,
+    DocumentEvents::DBUS_INTERFACE,
+    WindowEvents::DBUS_INTERFACE,
+    TerminalEvents::DBUS_INTERFACE,
+    MouseEvents::DBUS_INTERFACE,
+    FocusEvents::DBUS_INTERFACE,
+    KeyboardEvents::DBUS_INTERFACE,
+    CacheEvents::DBUS_INTERFACE,
+    EventListenerEvents::DBUS_INTERFACE,
+];
+
+/// One arm of a `match`: the string it matches and how often a signal took it.
+#[derive(Debug, Clone)]
+pub struct Arm {
+    pub name: &'static str,
+    /// The position of the interface in `INTERFACES`, or of the first category with this
+    /// member in its `CATEGORIES`.
+    pub index: usize,
+    pub count: u64,
+}
+
+/// The interface arms of atspi's match, in atspi's order.
+pub fn interface_arms(counters: &Counter) -> Vec<Arm> {
+    ATSPI_INTERFACE_ORDER
+        .iter()
+        .filter_map(|interface| {
+            let index = INTERFACES
+                .iter()
+                .position(|categories| categories[0].interface == *interface)?;
+            Some(Arm {
+                name: interface,
+                index,
+                count: counters.get_children()[index].total(),
+            })
+        })
+        .collect()
+}
+
+/// The member arms of atspi's match on the interface at `index` in `INTERFACES`, in atspi's
+/// order. Categories that share a member share an arm.
+pub fn member_arms(counters: &Counter, index: usize) -> Vec<Arm> {
+    let iface = &counters.get_children()[index];
+    let mut arms: Vec<Arm> = Vec::new();
+
+    for (index, category) in INTERFACES[index].iter().enumerate() {
+        let count = iface.get_children()[index].total();
+        match arms.iter_mut().find(|arm| arm.name == category.member) {
+            Some(arm) => arm.count = arm.count.saturating_add(count),
+            None => arms.push(Arm {
+                name: category.member,
+                index,
+                count,
+            }),
+        }
+    }
+
+    arms
+}
+
+/// `arms` ordered by how often they were taken, the most frequent first.
+/// Arms that were taken equally often keep their order.
+pub fn optimal_order(arms: &[Arm]) -> Vec<Arm> {
+    let mut optimal = arms.to_vec();
+    optimal.sort_by_key(|arm| std::cmp::Reverse(arm.count));
+    optimal
+}
+
+/// The mean number of string comparisons to find the arm of a signal, when the arms are
+/// tried in order.
+fn expected_comparisons(arms: &[Arm]) -> f64 {
+    let total: f64 = arms.iter().map(|arm| arm.count as f64).sum();
+    if total == 0.0 {
+        return 0.0;
+    }
+
+    arms.iter()
+        .enumerate()
+        .map(|(position, arm)| (position + 1) as f64 * arm.count as f64)
+        .sum::<f64>()
+        / total
+}
+
+fn print_savings(current: f64, optimal: f64) {
+    let savings = if current > 0.0 {
+        (current - optimal) / current * 100.0
+    } else {
+        0.0
+    };
+    println!("Expected comparisons per event: current {current:.3}, optimal {optimal:.3}");
+    println!("Projected savings: {savings:.2}%");
+}
+
+// Print the arms in the optimal order, with their share and their current position.
+fn print_order(heading: &str, arms: &[Arm]) {
+    let optimal = optimal_order(arms);
+    let total: u64 = arms.iter().map(|arm| arm.count).sum();
+
+    println!();
+    println!("{heading}:");
+    print_savings(expected_comparisons(arms), expected_comparisons(&optimal));
+    for (position, arm) in optimal.iter().enumerate() {
+        let current = arms
+            .iter()
+            .position(|other| other.name == arm.name)
+            .unwrap_or_default();
+        let percentage = if total > 0 {
+            arm.count as f64 / total as f64 * 100.0
+        } else {
+            0.0
+        };
+        print!("{:>3}. {:<40}", position + 1, arm.name);
+        print!("{:<10}", arm.count);
+        print!("{:<10}", format!("{percentage:.2}%"));
+        println!("(now {})", current + 1);
+    }
+}
+
+/// Print the order in which atspi should match interfaces and members, by the counts in the
+/// stats files in the current directory, and how many comparisons that would save.
+pub fn recommend() -> Result<(), Box<dyn Error>> {
+    let aggregate = read_stats_files()?;
+    let counters = &aggregate.counters;
+    println!("Files read: {}", aggregate.files);
+    println!("Total events: {}", counters.total());
+
+    let interfaces = interface_arms(counters);
+    print_order("Interface match order", &interfaces);
+
+    // Overall, an event costs the comparisons to find its interface plus those to find its
+    // member within that interface.
+    let total = counters.total() as f64;
+    let mut current = expected_comparisons(&interfaces);
+    let mut optimal = expected_comparisons(&optimal_order(&interfaces));
+
+    for arm in &interfaces {
+        let members = member_arms(counters, arm.index);
+        if total > 0.0 {
+            let share = arm.count as f64 / total;
+            current += share * expected_comparisons(&members);
+            optimal += share * expected_comparisons(&optimal_order(&members));
+        }
+        if members.len() < 2 || arm.count == 0 {
+            continue;
+        }
+
+        let name = interface_name(arm.name);
+        let (first, rest) = name.split_at(1);
+        print_order(
+            &format!("{}{rest} member match order", first.to_uppercase()),
+            &members,
+        );
+    }
+
+    println!();
+    println!("Overall:");
+    print_savings(current, optimal);
+
+    Ok(())
+}
@@ -0,0 +1,372 @@
+use atspi::events::{Event, ObjectEvents};
+use chrono::{DateTime, Local};
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+use tokio::{
+    process::{Child, Command},
+    signal::unix::{SignalKind, signal},
+};
+use tokio_stream::StreamExt;
+use zbus::Message;
+
+use crate::apps::{Apps, RESOLVE_TIMEOUT};
+use crate::capture::{CaptureReader, CaptureWriter};
+use crate::categories::{INTERFACES, find_category};
+use crate::counters::{Counter, CounterStats, new_counter_tree};
+use crate::details::ChildrenBursts;
+use crate::launch::{self, ProcessTree, Released};
+use crate::listeners::Listeners;
+use crate::matchers::match_event;
+use crate::raw::{self, RawCounts};
+use crate::setup::setup;
+use crate::unparsed::{UNHANDLED, UNKNOWN_SENDER, Unparsed, error_kind};
+use crate::writer::{print_raw_stats, print_stats, write_stats};
+
+/// Why a session stopped.
+enum Stop {
+    Signal(&'static str),
+    StreamEnded,
+    CommandExited(std::io::Result<std::process::ExitStatus>),
+}
+
+/// How to run a live session.
+#[derive(Debug, Default)]
+pub struct Options {
+    /// Also tally every signal from its header, see `RawCounts`.
+    pub raw: bool,
+    /// Append every signal to this capture file.
+    pub record: Option<PathBuf>,
+    /// Run this command and count only the events of its process tree, until it exits.
+    pub command: Vec<String>,
+}
+
+/// The counts of a live session.
+pub struct Session {
+    counters: Counter,
+    bursts: ChildrenBursts,
+    unparsed: Unparsed,
+    raw: RawCounts,
+    raw_mode: bool,
+    apps: Apps,
+    listeners: Listeners,
+    capture: Option<CaptureWriter>,
+    start: DateTime<Local>,
+}
+
+impl Session {
+    pub fn new(raw_mode: bool) -> Self {
+        Session {
+            counters: new_counter_tree(),
+            bursts: ChildrenBursts::new(),
+            unparsed: Unparsed::new(),
+            raw: RawCounts::new(),
+            raw_mode,
+            apps: Apps::new(),
+            listeners: Listeners::new(),
+            capture: None,
+            start: Local::now(),
+        }
+    }
+
+    /// Append every signal from now on to a new capture file at `path`.
+    pub fn record(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        self.capture = Some(CaptureWriter::create(path, self.start)?);
+        Ok(())
+    }
+
+    /// Look up the names of the applications that send events on `connection`.
+    pub fn resolve_apps(&mut self, connection: zbus::Connection) {
+        self.apps.resolve_on(connection);
+    }
+
+    /// Take the event listeners the registry on `connection` has now as active.
+    pub async fn add_registered_listeners(&mut self, connection: &zbus::Connection) {
+        let registered = self.listeners.registered_on(connection);
+        match tokio::time::timeout(RESOLVE_TIMEOUT, registered).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => eprintln!("Could not get the registered event listeners: {e}"),
+            Err(_) => eprintln!("Could not get the registered event listeners: no answer"),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn counters(&self) -> &Counter {
+        &self.counters
+    }
+
+    /// Count one message from the bus.
+    pub fn count(&mut self, msg: zbus::Result<Message>) {
+        let msg = match msg {
+            Ok(msg) => msg,
+            Err(e) => {
+                // No header to go by, but keep reading.
+                self.unparsed.add("", "", error_kind(&e.into()), 1);
+                return;
+            }
+        };
+        if let Some(capture) = &mut self.capture
+            && let Err(e) = capture.write_message(&msg)
+        {
+            // Keep counting, but don't leave a gap in the capture.
+            eprintln!("Error writing capture file, recording stopped: {e}");
+            self.capture = None;
+        }
+        if self.raw_mode {
+            self.raw.record(&msg);
+        }
+        if msg.message_type() != zbus::message::Type::Signal {
+            return;
+        }
+
+        match Event::try_from(&msg) {
+            Ok(ev) => {
+                if !match_event(&ev, &self.counters) {
+                    self.unparsed.record(&msg, UNHANDLED);
+                    return;
+                }
+                match &ev {
+                    Event::Object(ObjectEvents::ChildrenChanged(ev)) => self.bursts.record(ev),
+                    Event::Listener(lev) => self.listeners.record(lev),
+                    _ => {}
+                }
+                if let Some(sender) = msg.header().sender() {
+                    match_event(&ev, self.apps.counters(sender));
+                }
+            }
+            Err(e) => self.unparsed.record(&msg, error_kind(&e)),
+        }
+    }
+
+    /// Count a message given back by the process tree of the command. Signals of senders whose
+    /// process could not be found are not counted, but listed as unparsed.
+    pub fn count_released(&mut self, released: Released) {
+        match released {
+            Released::InTree(msg) => self.count(Ok(msg)),
+            Released::Unknown(msg) => {
+                if msg.message_type() == zbus::message::Type::Signal {
+                    self.unparsed.record(&msg, UNKNOWN_SENDER);
+                }
+            }
+        }
+    }
+
+    /// Count a signal known only by its sender, interface and member, as in a `dbus-monitor`
+    /// text dump.
+    ///
+    /// Without a body, members shared by several categories can't be told apart; these are
+    /// left unparsed, like other signals atspi would reject.
+    pub fn count_header(&mut self, sender: Option<&str>, interface: &str, member: &str) {
+        let Some(categories) = INTERFACES
+            .iter()
+            .find(|categories| categories[0].interface == interface)
+        else {
+            self.unparsed.add(interface, member, "InterfaceMatch", 1);
+            return;
+        };
+        if !categories.iter().any(|cat| cat.member == member) {
+            self.unparsed.add(interface, member, "MemberMatch", 1);
+            return;
+        }
+        match find_category(interface, member, "") {
+            Some((iface, index, _)) => {
+                self.counters.increment_index(iface, index);
+                if let Some(sender) = sender {
+                    self.apps.counters(sender).increment_index(iface, index);
+                }
+            }
+            None => self.unparsed.add(interface, member, "SignatureMatch", 1),
+        }
+    }
+
+    /// Write the stats file of the session that ended at `end` and print the stats.
+    pub fn finish(&mut self, end: DateTime<Local>) -> Result<(), Box<dyn Error>> {
+        if let Some(capture) = &mut self.capture
+            && let Err(e) = capture.flush()
+        {
+            eprintln!("Error writing capture file: {e}");
+        }
+        let written = write_stats(
+            &self.counters,
+            &self.unparsed,
+            &self.raw,
+            &self.bursts.counter(),
+            &self.apps,
+            self.start,
+            end,
+        );
+        self.print();
+
+        written
+    }
+
+    pub fn print(&self) {
+        print_stats(&self.counters, &self.bursts.counter(), &self.unparsed);
+        self.apps.print();
+        self.listeners.print();
+        if self.raw_mode {
+            print_raw_stats(&self.raw, &self.counters);
+        }
+    }
+}
+
+/// Count the signals in the capture file at `path`, as a live session would have counted them,
+/// then print the stats.
+pub fn replay(path: &Path, raw_mode: bool) -> Result<(), Box<dyn Error>> {
+    let capture = CaptureReader::open(path)?;
+    println!(
+        "Replaying capture of {} by atspi-counters {}",
+        capture.start.format("%Y-%m-%d %H:%M:%S"),
+        capture.tool_version
+    );
+
+    let mut session = Session::new(raw_mode);
+    let mut signals = 0u64;
+    for record in capture {
+        match record {
+            Ok(record) => session.count(record.to_message()),
+            Err(e) => {
+                // Recording stops mid-record if the session was killed; count what is there.
+                eprintln!("{}: stopped at a damaged record: {e}", path.display());
+                break;
+            }
+        }
+        signals += 1;
+    }
+
+    println!("Signals replayed: {signals}");
+    // No stats file is written: the session that recorded the capture wrote its own, and a
+    // second one would count the same signals twice in `--aggregate`.
+    session.print();
+    Ok(())
+}
+
+/// Count events until SIGINT, SIGTERM or SIGHUP arrives, the bus closes the stream or the
+/// command of `options` exits, then write and print the stats.
+///
+/// Exits with failure if the stream ended on its own, the command failed or the stats file
+/// could not be written.
+pub async fn run(options: Options) -> Result<ExitCode, Box<dyn Error>> {
+    let atspi = setup().await?;
+    if options.raw {
+        raw::add_match_rule(atspi.connection()).await?;
+    }
+
+    // Handled only once connected: while the connection is set up, which can hang on a bus
+    // that doesn't answer, there is nothing to write yet and a signal stops the program as usual.
+    let mut sigint = signal(SignalKind::interrupt())?;
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut sighup = signal(SignalKind::hangup())?;
+
+    let mut session = Session::new(options.raw);
+    session.resolve_apps(atspi.connection().clone());
+    // The registry is not part of the command's process tree, so its signals are not counted
+    // then and the registrations could only go stale.
+    // Asked before the messages are streamed: a stream that isn't read would hold up the reply.
+    if options.command.is_empty() {
+        session.add_registered_listeners(atspi.connection()).await;
+    }
+    // Read the messages rather than `atspi.event_stream()`, which drops the header of
+    // signals it fails to parse.
+    let mut messages = zbus::MessageStream::from(atspi.connection());
+    if let Some(path) = &options.record {
+        session.record(path)?;
+    }
+
+    // Start the command only now, so that none of its events are missed.
+    let mut child = None;
+    let mut tree = None;
+    if let Some((program, args)) = options.command.split_first() {
+        launch::add_match_rule(atspi.connection()).await?;
+        let command = Command::new(program)
+            .args(args)
+            .spawn()
+            .map_err(|e| format!("{program}: {e}"))?;
+        let pid = command
+            .id()
+            .ok_or_else(|| format!("{program}: exited at once"))?;
+        tree = Some(ProcessTree::new(pid, atspi.connection().clone()));
+        child = Some(command);
+        println!("Counting the events of {program} until it exits");
+    } else {
+        println!("Press Ctrl+C to stop the program and get stats");
+    }
+
+    let stop = loop {
+        tokio::select! {
+            _ = sigint.recv() => break Stop::Signal("SIGINT"),
+            _ = sigterm.recv() => break Stop::Signal("SIGTERM"),
+            _ = sighup.recv() => break Stop::Signal("SIGHUP"),
+            status = wait(&mut child) => break Stop::CommandExited(status),
+            released = looked_up(&mut tree) => {
+                for released in released {
+                    session.count_released(released);
+                }
+            }
+            msg = messages.next() => match (msg, &mut tree) {
+                (Some(Ok(msg)), Some(tree)) => {
+                    for released in tree.sent(msg) {
+                        session.count_released(released);
+                    }
+                }
+                (Some(msg), _) => session.count(msg),
+                (None, _) => break Stop::StreamEnded,
+            },
+        }
+    };
+
+    let mut exit_code = match stop {
+        Stop::Signal(name) => {
+            eprintln!("\nReceived {name}, stopping.");
+            ExitCode::SUCCESS
+        }
+        Stop::StreamEnded => {
+            eprintln!("\nThe event stream ended: the accessibility bus closed the connection.");
+            ExitCode::FAILURE
+        }
+        Stop::CommandExited(Ok(status)) => {
+            eprintln!("\nThe command exited ({status}), stopping.");
+            if status.success() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        Stop::CommandExited(Err(e)) => {
+            eprintln!("\nError waiting for the command, stopping: {e}");
+            ExitCode::FAILURE
+        }
+    };
+
+    // The messages still held wait for their senders, but only as long as one lookup takes.
+    if let Some(tree) = &mut tree {
+        for released in tree.finish(RESOLVE_TIMEOUT).await {
+            session.count_released(released);
+        }
+    }
+
+    if let Err(e) = session.finish(Local::now()) {
+        eprintln!("Error writing stats file: {e}");
+        exit_code = ExitCode::FAILURE;
+    }
+
+    Ok(exit_code)
+}
+
+// Wait for a sender of `tree` to be looked up, or forever if there is no tree.
+async fn looked_up(tree: &mut Option<ProcessTree>) -> Vec<Released> {
+    match tree {
+        Some(tree) => tree.looked_up().await,
+        None => std::future::pending().await,
+    }
+}
+
+// Wait for `child` to exit, or forever if there is none.
+async fn wait(child: &mut Option<Child>) -> std::io::Result<std::process::ExitStatus> {
+    match child {
+        Some(child) => child.wait().await,
+        None => std::future::pending().await,
+    }
+}
@@ -1,6 +1,7 @@
 use atspi::events::{
-    AvailableEvent, CacheEvents, DocumentEvents, EventListenerEvents, FocusEvents, KeyboardEvents,
-    MouseEvents, ObjectEvents, TerminalEvents, WindowEvents,
+    AvailableEvent, CacheEvents, DocumentEvents, EventListenerDeregisteredEvent,
+    EventListenerEvents, EventListenerRegisteredEvent, FocusEvents, KeyboardEvents, MouseEvents,
+    ObjectEvents, TerminalEvents, WindowEvents,
 };
 
 pub async fn setup() -> Result<atspi::AccessibilityConnection, atspi::AtspiError> {
@@ -12,6 +13,14 @@ pub async fn setup() -> Result<atspi::AccessibilityConnection, atspi::AtspiError
     atspi.register_event::<MouseEvents>().await?;
     atspi.register_event::<KeyboardEvents>().await?;
     atspi.register_event::<EventListenerEvents>().await?;
+    // The match rule of `EventListenerEvents` is for an interface the registry doesn't send
+    // them on; those of its members have the right one.
+    atspi
+        .add_match_rule::<EventListenerRegisteredEvent>()
+        .await?;
+    atspi
+        .add_match_rule::<EventListenerDeregisteredEvent>()
+        .await?;
     atspi.register_event::<CacheEvents>().await?;
     atspi.register_event::<FocusEvents>().await?;
     atspi.register_event::<AvailableEvent>().await?;
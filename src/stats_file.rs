@@ -0,0 +1,257 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, error::Error, path::Path};
+
+use crate::apps::Groups;
+use crate::counters::{Counter, CounterStats, Getters, new_counter_tree};
+use crate::raw::{RawCount, RawCounts};
+use crate::unparsed::{Unparsed, UnparsedCount};
+
+/// Version of the stats file layout.
+/// Bump this whenever a change to the layout would break older readers.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The contents of an `atspi-stats-<timestamp>.json` file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatsFile {
+    pub schema_version: u32,
+    pub tool_version: String,
+    /// Unknown for sessions migrated from legacy `.txt` files.
+    pub session_start: Option<DateTime<Local>>,
+    pub session_end: DateTime<Local>,
+    pub counters: CounterNode,
+    /// Signals that were received but not counted in `counters`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unparsed: Vec<UnparsedCount>,
+    /// Signals as tallied from their headers, for sessions run with `--raw`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub raw: Vec<RawCount>,
+    /// Runs of children-changed events for one parent, by length, if there were any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub children_bursts: Option<CounterNode>,
+    /// The counters of each application, by name, with only the counters that have events.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub applications: BTreeMap<String, CounterNode>,
+    /// The counters of each toolkit, by name and version, like those of `applications`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub toolkits: BTreeMap<String, CounterNode>,
+}
+
+/// The count of a counter and of its children.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CounterNode {
+    pub name: String,
+    pub count: u64,
+    /// The count reached `u64::MAX` and stopped there, so it is only a lower bound.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub saturated: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<CounterNode>,
+}
+
+/// The depth of the detail counters in the counter tree: interface → member → detail.
+const DETAIL_DEPTH: usize = 3;
+
+impl CounterNode {
+    pub fn new(counter: &Counter) -> Self {
+        CounterNode::at_depth(counter, 0)
+    }
+
+    /// The node of `counter` with only the children that have events.
+    pub fn active(counter: &Counter) -> Self {
+        CounterNode::at_depth(counter, DETAIL_DEPTH)
+    }
+
+    // Every interface and member is written, detail counters only if they have events.
+    // From `DETAIL_DEPTH` on, only counters with events are written.
+    fn at_depth(counter: &Counter, depth: usize) -> Self {
+        CounterNode {
+            name: counter.name().to_string(),
+            count: counter.total(),
+            saturated: counter.saturated(),
+            children: counter
+                .get_children()
+                .iter()
+                .filter(|child| depth + 1 < DETAIL_DEPTH || child.total() > 0)
+                .map(|child| CounterNode::at_depth(child, depth + 1))
+                .collect(),
+        }
+    }
+
+    /// Add the counts of this node and its children to `counter`, matching children by name.
+    pub fn add_to(&self, counter: &Counter) -> Result<(), Box<dyn Error>> {
+        if self.name != counter.name() {
+            return Err(format!(
+                "expected counter {:?}, found {:?}",
+                counter.name(),
+                self.name
+            )
+            .into());
+        }
+
+        // A saturated count stays saturated in the sum.
+        let count = if self.saturated { u64::MAX } else { self.count };
+        counter.add(&[], count);
+        for child in &self.children {
+            let counter_child = counter.child(&child.name).ok_or_else(|| {
+                format!("unknown counter {:?} in {:?}", child.name, counter.name())
+            })?;
+            child.add_to(counter_child)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl StatsFile {
+    pub fn new(
+        counters: &Counter,
+        unparsed: &Unparsed,
+        session_start: Option<DateTime<Local>>,
+        session_end: DateTime<Local>,
+    ) -> Self {
+        StatsFile {
+            unparsed: unparsed.counts(),
+            ..StatsFile::from_counter_node(CounterNode::new(counters), session_start, session_end)
+        }
+    }
+
+    pub fn from_counter_node(
+        counters: CounterNode,
+        session_start: Option<DateTime<Local>>,
+        session_end: DateTime<Local>,
+    ) -> Self {
+        StatsFile {
+            schema_version: SCHEMA_VERSION,
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            session_start,
+            session_end,
+            counters,
+            unparsed: Vec::new(),
+            raw: Vec::new(),
+            children_bursts: None,
+            applications: BTreeMap::new(),
+            toolkits: BTreeMap::new(),
+        }
+    }
+
+    /// Read a stats file of the current schema version.
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = std::fs::File::open(path)?;
+        let value: serde_json::Value = serde_json::from_reader(std::io::BufReader::new(file))?;
+
+        let schema_version = value
+            .get("schema_version")
+            .and_then(serde_json::Value::as_u64)
+            .ok_or_else(|| format!("{}: missing schema version", path.display()))?;
+        if schema_version != u64::from(SCHEMA_VERSION) {
+            return Err(format!(
+                "{}: schema version {schema_version} is not supported, expected {SCHEMA_VERSION}",
+                path.display()
+            )
+            .into());
+        }
+
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Write this file to `path`, through a temporary file next to it, so that `path`
+    /// never holds a partly written file.
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = Path::new(&temp);
+
+        let file = std::fs::File::create(temp)?;
+        let mut writer = std::io::BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        std::fs::rename(temp, path)?;
+        Ok(())
+    }
+
+    /// Add the counts in this file to `counters`, `bursts`, `unparsed` and `raw`, and those of
+    /// its applications and toolkits to the groups by the same name.
+    pub fn add_to(
+        &self,
+        counters: &Counter,
+        bursts: &Counter,
+        applications: &mut Groups,
+        toolkits: &mut Groups,
+        unparsed: &Unparsed,
+        raw: &RawCounts,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(node) = &self.children_bursts {
+            node.add_to(bursts)?;
+        }
+        for (nodes, groups) in [
+            (&self.applications, applications),
+            (&self.toolkits, toolkits),
+        ] {
+            for (name, node) in nodes {
+                node.add_to(groups.entry(name.clone()).or_insert_with(new_counter_tree))?;
+            }
+        }
+        for count in &self.unparsed {
+            unparsed.add(&count.interface, &count.member, &count.error, count.count);
+        }
+        for count in &self.raw {
+            raw.add(
+                &count.interface,
+                &count.member,
+                &count.signature,
+                count.count,
+            );
+        }
+        self.counters.add_to(counters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_read_back_as_written() {
+        let counters = new_counter_tree();
+        counters.increment_index(0, 0);
+        counters.increment_index(0, 3).increment_detail(&[0, 0]);
+        counters.increment_index(4, 0);
+        counters.add(&["focus", "focus"], u64::MAX);
+        let unparsed = Unparsed::new();
+        unparsed.add("org.a11y.atspi.Event.Object", "Bogus", "MemberMatch", 2);
+        let written = StatsFile::new(&counters, &unparsed, Some(Local::now()), Local::now());
+
+        let path =
+            std::env::temp_dir().join(format!("atspi-counters-stats-{}.json", std::process::id()));
+        written.write(&path).unwrap();
+        let read = StatsFile::read(&path);
+        std::fs::remove_file(&path).unwrap();
+        let read = read.unwrap();
+
+        assert_eq!(read.schema_version, SCHEMA_VERSION);
+        assert_eq!(read.session_start, written.session_start);
+        assert_eq!(read.session_end, written.session_end);
+        let summed = new_counter_tree();
+        let summed_unparsed = Unparsed::new();
+        read.add_to(
+            &summed,
+            &Counter::new("bursts"),
+            &mut Groups::new(),
+            &mut Groups::new(),
+            &summed_unparsed,
+            &RawCounts::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(CounterNode::new(&summed)).unwrap(),
+            serde_json::to_value(CounterNode::new(&counters)).unwrap()
+        );
+        assert!(summed.saturated());
+        assert_eq!(
+            serde_json::to_value(summed_unparsed.counts()).unwrap(),
+            serde_json::to_value(unparsed.counts()).unwrap()
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Mutex};
+
+/// The kind of signal that was received but not counted in the counter tree.
+pub const UNHANDLED: &str = "Unhandled";
+
+/// The kind of signal that was not counted because the process of its sender could not be
+/// found, when only the events of a command are counted.
+pub const UNKNOWN_SENDER: &str = "UnknownSender";
+
+/// The count of signals with one D-Bus interface and member that were not counted,
+/// and why: the kind of `AtspiError` they failed with, or `UNHANDLED` for events that
+/// atspi parsed but this tool has no counter for.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnparsedCount {
+    pub interface: String,
+    pub member: String,
+    pub error: String,
+    pub count: u64,
+}
+
+type UnparsedKey = (String, String, String);
+
+/// Signals that were not counted in the counter tree, by interface, member and error kind.
+///
+/// These are rare compared to parsed events, so a lock is fine here.
+#[derive(Debug, Default)]
+pub struct Unparsed {
+    counts: Mutex<HashMap<UnparsedKey, u64>>,
+}
+
+impl Unparsed {
+    pub fn new() -> Self {
+        Unparsed::default()
+    }
+
+    /// Count a signal that was not counted in the counter tree, by its message header.
+    pub fn record(&self, msg: &zbus::Message, error: &str) {
+        let header = msg.header();
+        let interface = header.interface().map_or("", |name| name.as_str());
+        let member = header.member().map_or("", |name| name.as_str());
+        self.add(interface, member, error, 1);
+    }
+
+    pub fn add(&self, interface: &str, member: &str, error: &str, count: u64) {
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        let total = counts
+            .entry((interface.to_string(), member.to_string(), error.to_string()))
+            .or_default();
+        *total = total.saturating_add(count);
+    }
+
+    /// All counts, the most frequent first.
+    pub fn counts(&self) -> Vec<UnparsedCount> {
+        let counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        let mut counts: Vec<UnparsedCount> = counts
+            .iter()
+            .map(|((interface, member, error), count)| UnparsedCount {
+                interface: interface.clone(),
+                member: member.clone(),
+                error: error.clone(),
+                count: *count,
+            })
+            .collect();
+        counts.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| (&a.interface, &a.member).cmp(&(&b.interface, &b.member)))
+        });
+        counts
+    }
+
+    pub fn total(&self) -> u64 {
+        let counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        counts
+            .values()
+            .fold(0, |total, count| total.saturating_add(*count))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_empty()
+    }
+
+    /// Print the signals that were not counted, if any.
+    pub fn print(&self) {
+        if self.is_empty() {
+            return;
+        }
+
+        println!();
+        println!("Unparsed stats:");
+        println!("Total events: {}", self.total());
+        for count in self.counts() {
+            let signal = format!("{}:{}", count.interface, count.member);
+            print!("{:<50}", signal);
+            print!("{:<20}", count.error);
+            println!("{}", count.count);
+        }
+    }
+}
+
+/// The kind of an atspi error, its variant name, e.g. "MemberMatch".
+pub fn error_kind(error: &atspi::AtspiError) -> &'static str {
+    use atspi::AtspiError::*;
+    match error {
+        Conversion(_) => "Conversion",
+        CacheVariantMismatch => "CacheVariantMismatch",
+        MemberMatch(_) => "MemberMatch",
+        InterfaceMatch(_) => "InterfaceMatch",
+        KindMatch(_) => "KindMatch",
+        InterfaceNotAvailable(_) => "InterfaceNotAvailable",
+        SignatureMatch(_) => "SignatureMatch",
+        UnknownInterface => "UnknownInterface",
+        MissingInterface => "MissingInterface",
+        MissingMember => "MissingMember",
+        UnknownRole(_) => "UnknownRole",
+        MissingName => "MissingName",
+        UnknownSignal => "UnknownSignal",
+        Owned(_) => "Owned",
+        Zbus(_) => "Zbus",
+        ZBusNames(_) => "ZBusNames",
+        Zvariant(_) => "Zvariant",
+        ParseError(_) => "ParseError",
+        PathConversionError(_) => "PathConversionError",
+        IO(_) => "IO",
+        IntConversionError(_) => "IntConversionError",
+        Infallible => "Infallible",
+        // `AtspiError` is non-exhaustive; variants added later are counted together.
+        _ => "Other",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atspi::AtspiError;
+
+    #[test]
+    fn error_kinds_are_variant_names() {
+        assert_eq!(
+            error_kind(&AtspiError::MemberMatch("a: b".to_string())),
+            "MemberMatch"
+        );
+        assert_eq!(error_kind(&AtspiError::UnknownSignal), "UnknownSignal");
+        assert_eq!(
+            error_kind(&AtspiError::Zbus("(org.a11y, Object)".to_string())),
+            "Zbus"
+        );
+    }
+}
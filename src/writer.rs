@@ -1,35 +1,142 @@
+use chrono::{DateTime, Local};
 use once_cell::sync::Lazy;
-use std::{
-    io::{BufWriter, Write},
-    sync::Arc,
-};
+use std::{collections::BTreeMap, error::Error, path::Path};
 
-use crate::counters::CounterStats;
+use crate::apps::{Apps, Groups};
+use crate::counters::{Counter, CounterStats, Getters};
+use crate::raw::RawCounts;
+use crate::stats_file::{CounterNode, StatsFile};
+use crate::unparsed::Unparsed;
 
-// Create a static filename atspi-stats-<timestamp>.txts
+// Create a static filename atspi-stats-<timestamp>.json
 // Create a file with the filename
 pub static FILENAME: Lazy<String> = Lazy::new(|| {
     let timestamp = chrono::Local::now().format("%Y-%m-%d-%H-%M-%S").to_string();
-    format!("atspi-stats-{}.txt", timestamp)
+    format!("atspi-stats-{}.json", timestamp)
 });
 
-pub fn write_stats(stats: &[Arc<dyn CounterStats>]) {
-    // Open the file
-    let file = std::fs::File::create(&*FILENAME).unwrap();
-    let mut writer = BufWriter::new(file);
-
-    // Write the stats to the file
-    for stat in stats {
-        writeln!(writer, "Total events: {}", stat.total()).unwrap();
-        for (cat, count) in stat.get_categories() {
-            write!(
-                writer,
-                "{}: {},",
-                cat,
-                count.load(std::sync::atomic::Ordering::Relaxed)
-            )
-            .unwrap();
+pub fn write_stats(
+    counters: &Counter,
+    unparsed: &Unparsed,
+    raw: &RawCounts,
+    bursts: &Counter,
+    apps: &Apps,
+    session_start: DateTime<Local>,
+    session_end: DateTime<Local>,
+) -> Result<(), Box<dyn Error>> {
+    StatsFile {
+        raw: raw.counts(),
+        children_bursts: (bursts.total() > 0).then(|| CounterNode::new(bursts)),
+        applications: active_nodes(&apps.applications()),
+        toolkits: active_nodes(&apps.toolkits()),
+        ..StatsFile::new(counters, unparsed, Some(session_start), session_end)
+    }
+    .write(Path::new(&*FILENAME))
+}
+
+fn active_nodes(groups: &Groups) -> BTreeMap<String, CounterNode> {
+    groups
+        .iter()
+        .map(|(name, counters)| (name.clone(), CounterNode::active(counters)))
+        .collect()
+}
+
+fn print_heading(name: &str) {
+    let (first, rest) = name.split_at(1);
+    println!("{}{rest} stats:", first.to_uppercase());
+}
+
+/// Print the interface totals, followed by the stats of each interface, the children-changed
+/// bursts of `ChildrenBursts` and the signals that were not counted.
+pub fn print_stats(counters: &Counter, bursts: &Counter, unparsed: &Unparsed) {
+    println!("\n\nStats:");
+    print_tables(counters);
+    if bursts.total() > 0 {
+        println!();
+        println!("Children-changed bursts per parent:");
+        bursts.pretty_print_units("bursts");
+    }
+    unparsed.print();
+}
+
+fn print_tables(counters: &Counter) {
+    if counters.any_saturated() {
+        println!(
+            "Some counters saturated at {}; their counts are lower bounds.",
+            u64::MAX
+        );
+    }
+    print_heading(counters.name());
+    counters.pretty_print_levels(1);
+
+    for iface in counters.get_children() {
+        if iface.get_children().is_empty() {
+            continue;
         }
-        writeln!(writer).unwrap();
+        println!();
+        print_heading(iface.name());
+        iface.pretty_print_stats();
+    }
+}
+
+/// Print the signals as tallied from their headers in the same tables as the parsed events,
+/// then by body signature, then where they disagree with `parsed`, the events parsed from
+/// the same signals.
+pub fn print_raw_stats(raw: &RawCounts, parsed: &Counter) {
+    let (raw_counters, unknown) = raw.counter_tree();
+
+    println!("\n\nRaw stats:");
+    print_tables(&raw_counters);
+
+    println!();
+    println!("Signals by signature:");
+    for count in raw.counts() {
+        let signal = format!("{}:{}", count.interface, count.member);
+        print!("{:<50}", signal);
+        print!("{:<30}", count.signature);
+        println!("{}", count.count);
+    }
+
+    println!();
+    println!("Discrepancy (raw - parsed):");
+    print!("{:<50}", "");
+    print!("{:<12}", "raw");
+    print!("{:<12}", "parsed");
+    println!("difference");
+    let mut consistent = true;
+    for (raw_iface, parsed_iface) in raw_counters
+        .get_children()
+        .iter()
+        .zip(parsed.get_children())
+    {
+        for (raw_member, parsed_member) in raw_iface
+            .get_children()
+            .iter()
+            .zip(parsed_iface.get_children())
+        {
+            if raw_member.total() == parsed_member.total() {
+                continue;
+            }
+            consistent = false;
+            let name = format!("{}:{}", raw_iface.name(), raw_member.name());
+            print!("{:<50}", name);
+            print!("{:<12}", raw_member.total());
+            print!("{:<12}", parsed_member.total());
+            println!(
+                "{}",
+                i128::from(raw_member.total()) - i128::from(parsed_member.total())
+            );
+        }
+    }
+    for count in &unknown {
+        consistent = false;
+        let signal = format!("{}:{} ({})", count.interface, count.member, count.signature);
+        print!("{:<50}", signal);
+        print!("{:<12}", count.count);
+        print!("{:<12}", "-");
+        println!("{}", count.count);
+    }
+    if consistent {
+        println!("none");
     }
 }